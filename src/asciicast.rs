@@ -0,0 +1,385 @@
+//
+// asciicast v2 files (asciinema)
+//
+// A header object on the first line, then one event per line:
+//
+// {"version": 2, "width": 80, "height": 24, "timestamp": 1700000000}
+// [0.248, "o", "$ ls\r\n"]
+// [1.002, "r", "100x30"]
+//
+// https://docs.asciinema.org/manual/asciicast/v2/
+//
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs,
+    iter::Peekable,
+    path::Path,
+    str::Chars,
+};
+
+pub struct Header {
+    pub width: i64,
+    pub height: i64,
+    pub idle_time_limit: Option<f64>,
+}
+
+pub enum Event {
+    Output(String),
+    Resize(i64, i64),
+}
+
+// A string as a JSON string literal
+pub fn string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+// Just enough JSON for the header and the events
+enum Value {
+    Null,
+    Bool,
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(HashMap<String, Value>),
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("expected '{}', found '{}'", expected, c)),
+            None => Err(format!("expected '{}'", expected)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('"') => self.string().map(Value::String),
+            Some('[') => {
+                self.chars.next();
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.chars.next_if_eq(&']').is_some() {
+                    return Ok(Value::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.chars.next() {
+                        Some(',') => continue,
+                        Some(']') => return Ok(Value::Array(items)),
+                        _ => return Err("unterminated array".to_string()),
+                    }
+                }
+            }
+            Some('{') => {
+                self.chars.next();
+                let mut members = HashMap::new();
+                self.skip_whitespace();
+                if self.chars.next_if_eq(&'}').is_some() {
+                    return Ok(Value::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    members.insert(key, self.value()?);
+                    self.skip_whitespace();
+                    match self.chars.next() {
+                        Some(',') => continue,
+                        Some('}') => return Ok(Value::Object(members)),
+                        _ => return Err("unterminated object".to_string()),
+                    }
+                }
+            }
+            Some(_) => {
+                let mut word = String::new();
+                while let Some(c) = self.chars.next_if(|c| c.is_alphanumeric() || "+-.".contains(*c)) {
+                    word.push(c);
+                }
+                match word.as_str() {
+                    "null" => Ok(Value::Null),
+                    "true" | "false" => Ok(Value::Bool),
+                    number => number.parse::<f64>().ok()
+                        .filter(|value| is_number(number) && value.is_finite())
+                        .map(Value::Number)
+                        .ok_or_else(|| format!("invalid value \"{}\"", number)),
+                }
+            }
+            None => Err("unexpected end of line".to_string()),
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits: String = (0..4).filter_map(|_| self.chars.next()).collect();
+        if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("invalid escape \\u{}", digits));
+        }
+        u32::from_str_radix(&digits, 16).map_err(|_| format!("invalid escape \\u{}", digits))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(text),
+                Some('\\') => match self.chars.next() {
+                    Some('n') => text.push('\n'),
+                    Some('r') => text.push('\r'),
+                    Some('t') => text.push('\t'),
+                    Some('b') => text.push('\u{8}'),
+                    Some('f') => text.push('\u{c}'),
+                    Some('u') => {
+                        let mut code = self.hex4()?;
+                        // a character outside the BMP comes as a surrogate pair
+                        if (0xd800..0xdc00).contains(&code) {
+                            if self.chars.next_if_eq(&'\\').is_none() || self.chars.next_if_eq(&'u').is_none() {
+                                return Err("unpaired surrogate".to_string());
+                            }
+                            let low = self.hex4()?;
+                            if !(0xdc00..0xe000).contains(&low) {
+                                return Err("unpaired surrogate".to_string());
+                            }
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                        }
+                        text.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    }
+                    Some(c) if "\"\\/".contains(c) => text.push(c),
+                    Some(_) => return Err("invalid escape".to_string()),
+                    None => return Err("unterminated string".to_string()),
+                },
+                Some(c) => text.push(c),
+                None => return Err("unterminated string".to_string()),
+            }
+        }
+    }
+}
+
+// Whether `text` follows the JSON grammar of numbers, which has no NaN,
+// infinity, leading zeros or "+" sign
+fn is_number(text: &str) -> bool {
+    fn digits(text: &str) -> usize {
+        text.bytes().take_while(u8::is_ascii_digit).count()
+    }
+
+    let text = text.strip_prefix('-').unwrap_or(text);
+    let integer = digits(text);
+    if integer == 0 || (integer > 1 && text.starts_with('0')) {
+        return false;
+    }
+    let mut rest = &text[integer..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let count = digits(fraction);
+        if count == 0 {
+            return false;
+        }
+        rest = &fraction[count..];
+    }
+    if let Some(exponent) = rest.strip_prefix(['e', 'E']) {
+        let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        let count = digits(exponent);
+        if count == 0 {
+            return false;
+        }
+        rest = &exponent[count..];
+    }
+    rest.is_empty()
+}
+
+fn parse(line: &str) -> Result<Value, String> {
+    let mut parser = Parser { chars: line.chars().peekable() };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.chars.peek() {
+        Some(_) => Err("unexpected text after the value".to_string()),
+        None => Ok(value),
+    }
+}
+
+fn size(text: &str) -> Option<(i64, i64)> {
+    let (columns, rows) = text.split_once('x')?;
+    Some((columns.trim().parse().ok()?, rows.trim().parse().ok()?))
+}
+
+// Reads a recording; events other than output and resizes are left out
+pub fn read(path: &Path) -> Result<(Header, Vec<(f64, Event)>), String> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("{}: {}", path.display(), err))?;
+    parse_recording(&text, &path.display().to_string())
+}
+
+// Parses the text of a recording, `name` being the file in the messages
+fn parse_recording(text: &str, name: &str) -> Result<(Header, Vec<(f64, Event)>), String> {
+    let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+
+    let Some((_, first)) = lines.next() else {
+        return Err(format!("{}: empty file", name));
+    };
+    let Value::Object(header) = parse(first).map_err(|err| format!("{}:1: {}", name, err))? else {
+        return Err(format!("{}:1: the header is not an object", name));
+    };
+    let number = |key: &str| match header.get(key) {
+        Some(Value::Number(n)) => Some(*n),
+        _ => None,
+    };
+    if number("version") != Some(2.0) {
+        return Err(format!("{}: only asciicast version 2 is supported", name));
+    }
+    let header = Header {
+        width: number("width").unwrap_or(80.0) as i64,
+        height: number("height").unwrap_or(24.0) as i64,
+        idle_time_limit: number("idle_time_limit").filter(|limit| *limit > 0.0),
+    };
+
+    let mut events = Vec::new();
+    for (i, line) in lines {
+        let event = parse(line).map_err(|err| format!("{}:{}: {}", name, i + 1, err))?;
+        let Value::Array(items) = event else {
+            return Err(format!("{}:{}: the event is not an array", name, i + 1));
+        };
+        match items.as_slice() {
+            [Value::Number(time), Value::String(code), Value::String(data)] => match code.as_str() {
+                "o" => events.push((*time, Event::Output(data.clone()))),
+                "r" => match size(data) {
+                    Some((columns, rows)) => events.push((*time, Event::Resize(columns, rows))),
+                    None => eprintln!("Warning: invalid size \"{}\" in {}:{}", data, name, i + 1),
+                },
+                // input and markers are not replayed
+                _ => {}
+            },
+            _ => return Err(format!("{}:{}: invalid event", name, i + 1)),
+        }
+    }
+
+    Ok((header, events))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_string(json: &str) -> Result<String, String> {
+        match parse(json)? {
+            Value::String(text) => Ok(text),
+            _ => Err("not a string".to_string()),
+        }
+    }
+
+    #[test]
+    fn unicode_escapes() {
+        assert_eq!(parse_string(r#""\u0041\u00e9\u001b[0m""#).unwrap(), "Aé\x1b[0m");
+        assert_eq!(parse_string(r#""\ud83d\ude00""#).unwrap(), "😀");
+        assert!(parse_string(r#""\ud83d""#).is_err());
+        assert!(parse_string(r#""\ud83d\u0041""#).is_err());
+        assert!(parse_string(r#""\ud83d \ude00""#).is_err());
+        assert!(parse_string(r#""\u+041""#).is_err());
+        assert!(parse_string(r#""\u12""#).is_err());
+        // a lone low surrogate is not a character
+        assert_eq!(parse_string(r#""\ude00""#).unwrap(), "\u{fffd}");
+    }
+
+    #[test]
+    fn simple_escapes() {
+        assert_eq!(parse_string(r#""\"\\\/\n\t""#).unwrap(), "\"\\/\n\t");
+        for json in [r#""\a""#, r#""\x41""#, r#""\'""#, r#""\ ""#] {
+            assert!(parse_string(json).is_err(), "{:?}", json);
+        }
+    }
+
+    #[test]
+    fn numbers() {
+        for (json, value) in [("0", 0.0), ("-1.5", -1.5), ("2e3", 2000.0), ("1.25E-2", 0.0125), ("0.5e+1", 5.0)] {
+            assert!(matches!(parse(json), Ok(Value::Number(number)) if number == value), "{:?}", json);
+        }
+        for json in ["NaN", "nan", "inf", "-inf", "infinity", "Infinity", "1e999", "+1", "01", "1.", ".5", "1e", "0x10", "1_0"] {
+            assert!(parse(json).is_err(), "{:?}", json);
+        }
+    }
+
+    #[test]
+    fn invalid_lines() {
+        for line in ["", "[", "[1.0, \"o\"", "[1.0 \"o\"]", "{\"a\": }", "\"open", "\"open\\", "[1.0, \"o\", \"x\"] more", "nope"] {
+            assert!(parse(line).is_err(), "{:?}", line);
+        }
+        assert!(parse(" [1.0, \"o\", \"x\"] ").is_ok());
+    }
+
+    #[test]
+    fn recording() {
+        let text = concat!(
+            "{\"version\": 2, \"width\": 100, \"height\": 30, \"idle_time_limit\": 1.5}\n",
+            "[0.5, \"o\", \"$ ls\\r\\n\"]\n",
+            "\n",
+            "[0.7, \"i\", \"q\"]\n",
+            "[1.0, \"r\", \"120x40\"]\n",
+        );
+        let (header, events) = parse_recording(text, "test").unwrap();
+        assert_eq!((header.width, header.height), (100, 30));
+        assert_eq!(header.idle_time_limit, Some(1.5));
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[0], (time, Event::Output(data)) if *time == 0.5 && data == "$ ls\r\n"));
+        assert!(matches!(events[1], (time, Event::Resize(120, 40)) if time == 1.0));
+    }
+
+    #[test]
+    fn recording_header() {
+        let (header, _) = parse_recording("{\"version\": 2}", "test").unwrap();
+        assert_eq!((header.width, header.height), (80, 24));
+        assert_eq!(header.idle_time_limit, None);
+        let (header, _) = parse_recording("{\"version\": 2, \"idle_time_limit\": 0}", "test").unwrap();
+        assert_eq!(header.idle_time_limit, None);
+
+        assert!(parse_recording("", "test").is_err());
+        assert!(parse_recording("{\"version\": 1}", "test").is_err());
+        assert!(parse_recording("[2]", "test").is_err());
+    }
+
+    #[test]
+    fn invalid_events() {
+        let header = "{\"version\": 2}\n";
+        let error = parse_recording(&format!("{}[0.5, \"o\"", header), "test").err().unwrap();
+        assert!(error.starts_with("test:2: "), "{}", error);
+        assert!(parse_recording(&format!("{}{{}}", header), "test").is_err());
+        assert!(parse_recording(&format!("{}[0.5, \"o\", 3]", header), "test").is_err());
+        // a size that cannot be read is skipped
+        let (_, events) = parse_recording(&format!("{}[0.5, \"r\", \"wide\"]", header), "test").unwrap();
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn string_round_trip() {
+        for text in ["", "plain", "\"quoted\" \\ back", "\r\n\t", "\x1b[1;31mred\x1b[0m\x07", "é 😀 \u{7f} \u{85}"] {
+            assert_eq!(parse_string(&string(text)).unwrap(), text);
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//
+// Terminal background
+//
+// Read from the [Settings] section of termint.ini:
+//
+// background_image =             (relative to the config directory, or ~/...)
+// background_mode = cover        (cover, tile or center)
+// background_opacity = 1.0       (0.0 .. 1.0, only the background fades)
+//
+// The image is drawn behind the terminals, which then leave their own
+// background transparent. The win.toggle-opacity action makes a window
+// opaque and back.
+//
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use gtk4::{
+    gdk::RGBA,
+    gio,
+    prelude::*,
+    ApplicationWindow,
+};
+
+use ini::Properties;
+
+use vte4::{
+    Terminal,
+    TerminalExt,
+};
+
+// The class of termint windows, to keep the styles off other windows
+pub const WINDOW_CLASS: &str = "termint";
+const OPAQUE_CLASS: &str = "opaque";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Cover,
+    Tile,
+    Center,
+}
+
+#[derive(Debug, Clone)]
+pub struct Background {
+    image: Option<PathBuf>,
+    mode: Mode,
+    opacity: f64,
+}
+
+// An image path: absolute, under the home directory or in `config_dir`
+fn image_path(value: &str, config_dir: &Path) -> PathBuf {
+    match value.strip_prefix("~/") {
+        Some(rest) => PathBuf::from(env::var("HOME").unwrap_or_default()).join(rest),
+        None => config_dir.join(value),
+    }
+}
+
+impl Background {
+
+    pub fn default_settings(settings: &mut ini::SectionSetter) {
+        settings
+            .set("background_image", "")
+            .set("background_mode", "cover")
+            .set("background_opacity", "1.0");
+    }
+
+    pub fn from_settings(settings: &Properties, config_dir: &Path) -> Self {
+        let image = settings.get("background_image")
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .and_then(|value| {
+                let path = image_path(value, config_dir);
+                if path.is_file() {
+                    Some(path)
+                } else {
+                    eprintln!("Warning: invalid value \"{}\" for \"background_image\"", value);
+                    None
+                }
+            });
+
+        let mode = match settings.get("background_mode").map(str::trim) {
+            None | Some("cover") => Mode::Cover,
+            Some("tile") => Mode::Tile,
+            Some("center") => Mode::Center,
+            Some(value) => {
+                eprintln!("Warning: invalid value \"{}\" for \"background_mode\"", value);
+                Mode::Cover
+            }
+        };
+
+        let opacity = match settings.get("background_opacity").map(str::trim) {
+            Some(value) => match value.parse::<f64>() {
+                Ok(opacity) if (0.0..=1.0).contains(&opacity) => opacity,
+                _ => {
+                    eprintln!("Warning: invalid value \"{}\" for \"background_opacity\"", value);
+                    1.0
+                }
+            },
+            None => 1.0,
+        };
+
+        Background {
+            image,
+            mode,
+            opacity,
+        }
+    }
+
+    fn translucent(&self) -> bool {
+        self.image.is_some() || self.opacity < 1.0
+    }
+
+    // The styles of the windows, panes and image
+    pub fn css(&self) -> String {
+        if !self.translucent() {
+            return String::new();
+        }
+
+        let mut css = format!(r#"
+window.{0} {{
+    background-color: transparent;
+}}
+window.{0} notebook > stack {{
+    background-color: transparent;
+}}"#, WINDOW_CLASS);
+
+        if let Some(image) = &self.image {
+            let url = gio::File::for_path(image).uri();
+            let (size, repeat, position) = match self.mode {
+                Mode::Cover => ("cover", "no-repeat", "center"),
+                Mode::Tile => ("auto", "repeat", "left top"),
+                Mode::Center => ("auto", "no-repeat", "center"),
+            };
+            css.push_str(&format!(r#"
+window.{0} .scrolled-window {{
+    background-image: cross-fade({1}% url("{2}"));
+    background-size: {3};
+    background-repeat: {4};
+    background-position: {5};
+}}
+window.{0}.{6} .scrolled-window {{
+    background-image: url("{2}");
+}}"#, WINDOW_CLASS, (self.opacity * 100.0).round(), url, size, repeat, position, OPAQUE_CLASS));
+        }
+
+        css
+    }
+
+    // Sets the alpha of the terminal background: none over an image,
+    // background_opacity otherwise
+    pub fn apply(&self, terminal: &Terminal, color: Option<&RGBA>) {
+        if !self.translucent() {
+            return;
+        }
+        let mut color = color.copied().unwrap_or(RGBA::BLACK);
+        let alpha = if self.image.is_some() {
+            0.0
+        } else if is_opaque(terminal) {
+            1.0
+        } else {
+            color.alpha() * self.opacity as f32
+        };
+        color.set_alpha(alpha);
+        terminal.set_color_background(&color);
+    }
+}
+
+// Whether the window of a terminal was made opaque
+fn is_opaque(terminal: &Terminal) -> bool {
+    terminal.root().is_some_and(|root| root.has_css_class(OPAQUE_CLASS))
+}
+
+// Switches a window between translucent and opaque
+pub fn toggle_opacity(window: &ApplicationWindow) {
+    if window.has_css_class(OPAQUE_CLASS) {
+        window.remove_css_class(OPAQUE_CLASS);
+    } else {
+        window.add_css_class(OPAQUE_CLASS);
+    }
+}
@@ -0,0 +1,222 @@
+//
+// Copy and paste
+//
+// Read from the [Settings] section of termint.ini:
+//
+// copy_on_select = primary       (primary, clipboard or none)
+// confirm_paste = true           (ask before pasting line breaks or sudo)
+// paste_strip_control = false    (drop control and escape characters)
+//
+// confirm_paste only asks while the program in the terminal has not
+// turned on bracketed paste, which the PTY proxy watches for, as such
+// programs do not run pasted lines on their own. Escape sequences, which
+// could end the bracketed paste early, are always asked for. With
+// confirm_paste = false the proxy is not needed for it.
+//
+
+use gtk4::{
+    gdk,
+    gio::Cancellable,
+    prelude::*,
+    Align,
+    Button,
+    Label,
+    Orientation,
+    Window,
+};
+
+use vte4::{
+    Format,
+    Terminal,
+    TerminalExt,
+};
+
+use std::rc::Rc;
+
+use crate::{config::Config, get_bool, proxy::Stream};
+
+// Longest part of the text shown in the confirmation dialog
+const PREVIEW_LENGTH: usize = 400;
+
+pub fn default_settings(settings: &mut ini::SectionSetter) {
+    settings
+        .set("copy_on_select", "primary")
+        .set("confirm_paste", "true")
+        .set("paste_strip_control", "false");
+}
+
+// Copies a new selection as copy_on_select says
+pub fn copy_on_select(terminal: &Terminal, config: &Config) {
+    if !terminal.has_selection() {
+        return;
+    }
+    let ini = config.ini();
+    let mode = ini.section(Some("Settings"))
+        .and_then(|settings| settings.get("copy_on_select"))
+        .map(str::trim)
+        .unwrap_or("primary");
+    match mode {
+        "primary" => terminal.copy_primary(),
+        "clipboard" => {
+            terminal.copy_primary();
+            terminal.copy_clipboard_format(Format::Text);
+        }
+        "none" => {}
+        value => eprintln!("Warning: invalid value \"{}\" for \"copy_on_select\"", value),
+    }
+}
+
+// Follows bracketed paste in the output of a terminal while
+// confirm_paste is on
+pub fn follow_modes(stream: &Rc<Stream>, ini: &ini::Ini) {
+    let confirm = ini.section(Some("Settings"))
+        .map(|settings| get_bool(settings, "confirm_paste", true))
+        .unwrap_or(true);
+    stream.follow_modes(confirm);
+}
+
+// Pastes the CLIPBOARD (or the PRIMARY selection), filtered and confirmed
+// as the settings say. The stream of the terminal tells whether bracketed
+// paste is on.
+pub fn paste(terminal: &Terminal, stream: Option<Rc<Stream>>, config: &Config, primary: bool) {
+    let (confirm, strip) = {
+        let ini = config.ini();
+        match ini.section(Some("Settings")) {
+            Some(settings) => (
+                get_bool(settings, "confirm_paste", true),
+                get_bool(settings, "paste_strip_control", false),
+            ),
+            None => (true, false),
+        }
+    };
+
+    let clipboard = if primary {
+        terminal.primary_clipboard()
+    } else {
+        terminal.clipboard()
+    };
+
+    let terminal = terminal.downgrade();
+    clipboard.read_text_async(None::<&Cancellable>, move |text| {
+        let (Some(terminal), Ok(Some(text))) = (terminal.upgrade(), text) else {
+            return;
+        };
+        let text = if strip {
+            strip_control(&text)
+        } else {
+            text.to_string()
+        };
+        let bracketed = stream.is_some_and(|stream| stream.bracketed_paste());
+        match risk(&text, bracketed) {
+            Some(reason) if confirm => ask(&terminal, text, reason),
+            _ => terminal.paste_text(&text),
+        }
+    });
+}
+
+// Removes the control characters but tabs and line breaks, which takes
+// out escape sequences that could fake bracketed paste or keystrokes
+fn strip_control(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r'))
+        .collect()
+}
+
+// Why a text should not be pasted without asking; `text` is what is
+// pasted, line breaks at the end included
+fn risk(text: &str, bracketed: bool) -> Option<String> {
+    if text.contains('\x1b') {
+        Some("The text has escape sequences, which may act as keys.".to_string())
+    } else if bracketed {
+        None
+    } else if text.lines().any(runs_sudo) {
+        Some("The text runs a command with sudo.".to_string())
+    } else if text.contains(['\n', '\r']) {
+        Some("The text has line breaks and may run commands as soon as it is pasted.".to_string())
+    } else {
+        None
+    }
+}
+
+// Whether a line has sudo as a word of its own
+fn runs_sudo(line: &str) -> bool {
+    line.split(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-')))
+        .any(|word| word == "sudo")
+}
+
+// Asks before pasting
+fn ask(terminal: &Terminal, text: String, reason: String) {
+    let dialog = Window::builder()
+        .title("Paste")
+        .modal(true)
+        .resizable(false)
+        .build();
+    if let Some(parent) = terminal.root().and_downcast::<Window>() {
+        dialog.set_transient_for(Some(&parent));
+    }
+
+    let preview: String = text.chars().take(PREVIEW_LENGTH).collect();
+    let preview = Label::builder()
+        .label(if preview.len() < text.len() { format!("{}…", preview) } else { preview })
+        .selectable(true)
+        .wrap(true)
+        .max_width_chars(60)
+        .halign(Align::Start)
+        .css_classes(["monospace"])
+        .build();
+
+    let cancel = Button::with_label("Cancel");
+    let paste = Button::with_label("Paste");
+    paste.add_css_class("destructive-action");
+
+    let buttons = gtk4::Box::new(Orientation::Horizontal, 8);
+    buttons.set_halign(Align::End);
+    buttons.append(&cancel);
+    buttons.append(&paste);
+
+    let content = gtk4::Box::new(Orientation::Vertical, 12);
+    content.set_margin_top(16);
+    content.set_margin_bottom(16);
+    content.set_margin_start(16);
+    content.set_margin_end(16);
+    content.append(&Label::builder().label(reason).halign(Align::Start).build());
+    content.append(&preview);
+    content.append(&buttons);
+    dialog.set_child(Some(&content));
+
+    let dialog_weak = dialog.downgrade();
+    cancel.connect_clicked(move |_| {
+        if let Some(dialog) = dialog_weak.upgrade() {
+            dialog.close();
+        }
+    });
+
+    let dialog_weak = dialog.downgrade();
+    let terminal_weak = terminal.downgrade();
+    paste.connect_clicked(move |_| {
+        if let Some(terminal) = terminal_weak.upgrade() {
+            terminal.paste_text(&text);
+            terminal.grab_focus();
+        }
+        if let Some(dialog) = dialog_weak.upgrade() {
+            dialog.close();
+        }
+    });
+
+    // Escape cancels
+    let keys = gtk4::EventControllerKey::new();
+    let dialog_weak = dialog.downgrade();
+    keys.connect_key_pressed(move |_, key, _, _| {
+        if key == gdk::Key::Escape {
+            if let Some(dialog) = dialog_weak.upgrade() {
+                dialog.close();
+            }
+            return gtk4::glib::Propagation::Stop;
+        }
+        gtk4::glib::Propagation::Proceed
+    });
+    dialog.add_controller(keys);
+
+    cancel.grab_focus();
+    dialog.present();
+}
@@ -0,0 +1,165 @@
+//
+// Terminal colours
+//
+// The [Colors] section of termint.ini sets the foreground, background,
+// cursor, highlight and bold colours and the 16 colour palette
+// (color0 .. color15). Any CSS colour works: "#2e3436", "rgb(46,52,54)",
+// "rgba(0,0,0,0.8)" or a name like "black". A built-in "theme" can be
+// picked as a starting point; the other keys override it.
+//
+
+use gtk4::gdk::RGBA;
+
+use ini::{Ini, Properties};
+
+use vte4::{
+    Terminal,
+    TerminalExt,
+    TerminalExtManual,
+};
+
+pub const PALETTE_SIZE: usize = 16;
+
+// (name, foreground, background, palette)
+type Theme = (&'static str, &'static str, &'static str, [&'static str; PALETTE_SIZE]);
+
+const THEMES: &[Theme] = &[
+    ("tango", "#d3d7cf", "#2e3436", [
+        "#2e3436", "#cc0000", "#4e9a06", "#c4a000", "#3465a4", "#75507b", "#06989a", "#d3d7cf",
+        "#555753", "#ef2929", "#8ae234", "#fce94f", "#729fcf", "#ad7fa8", "#34e2e2", "#eeeeec",
+    ]),
+    ("linux", "#aaaaaa", "#000000", [
+        "#000000", "#aa0000", "#00aa00", "#aa5500", "#0000aa", "#aa00aa", "#00aaaa", "#aaaaaa",
+        "#555555", "#ff5555", "#55ff55", "#ffff55", "#5555ff", "#ff55ff", "#55ffff", "#ffffff",
+    ]),
+    ("solarized-dark", "#839496", "#002b36", [
+        "#073642", "#dc322f", "#859900", "#b58900", "#268bd2", "#d33682", "#2aa198", "#eee8d5",
+        "#002b36", "#cb4b16", "#586e75", "#657b83", "#839496", "#6c71c4", "#93a1a1", "#fdf6e3",
+    ]),
+    ("solarized-light", "#657b83", "#fdf6e3", [
+        "#073642", "#dc322f", "#859900", "#b58900", "#268bd2", "#d33682", "#2aa198", "#eee8d5",
+        "#002b36", "#cb4b16", "#586e75", "#657b83", "#839496", "#6c71c4", "#93a1a1", "#fdf6e3",
+    ]),
+];
+
+const DEFAULT_THEME: &str = "tango";
+
+#[derive(Debug, Clone, Default)]
+pub struct Colors {
+    foreground: Option<RGBA>,
+    background: Option<RGBA>,
+    cursor: Option<RGBA>,
+    cursor_foreground: Option<RGBA>,
+    highlight: Option<RGBA>,
+    highlight_foreground: Option<RGBA>,
+    bold: Option<RGBA>,
+    palette: Vec<RGBA>,
+}
+
+fn parse_color(key: &str, value: &str) -> Option<RGBA> {
+    match RGBA::parse(value.trim()) {
+        Ok(color) => Some(color),
+        Err(_) => {
+            eprintln!("Warning: invalid color \"{}\" for \"{}\"", value, key);
+            None
+        }
+    }
+}
+
+fn theme(name: &str) -> Option<&'static Theme> {
+    let theme = THEMES.iter().find(|(theme, ..)| theme.eq_ignore_ascii_case(name.trim()));
+    if theme.is_none() {
+        eprintln!("Warning: unknown color theme \"{}\"", name);
+    }
+    theme
+}
+
+impl Colors {
+
+    // Writes the [Colors] section: the default theme plus every key left
+    // empty, ready to be filled in to override the theme
+    pub fn default_section(ini: &mut Ini) {
+        let mut section = ini.with_section(Some("Colors"));
+        section.set("theme", DEFAULT_THEME);
+        for key in ["foreground", "background", "cursor", "cursor_foreground",
+                    "highlight", "highlight_foreground", "bold"] {
+            section.set(key, "");
+        }
+        for i in 0..PALETTE_SIZE {
+            section.set(format!("color{}", i), "");
+        }
+    }
+
+    pub fn from_section(section: Option<&Properties>) -> Self {
+        let Some(section) = section else {
+            return Self::default();
+        };
+
+        // a key that is missing or left empty means "not set"
+        let color = |key: &str| {
+            section.get(key)
+                .filter(|value| !value.trim().is_empty())
+                .and_then(|value| parse_color(key, value))
+        };
+
+        let mut colors = Colors {
+            foreground: color("foreground"),
+            background: color("background"),
+            cursor: color("cursor"),
+            cursor_foreground: color("cursor_foreground"),
+            highlight: color("highlight"),
+            highlight_foreground: color("highlight_foreground"),
+            bold: color("bold"),
+            palette: Vec::new(),
+        };
+
+        let base = section.get("theme")
+            .filter(|name| !name.trim().is_empty())
+            .and_then(theme);
+
+        if let Some((_, foreground, background, palette)) = base {
+            colors.foreground = colors.foreground.or_else(|| RGBA::parse(*foreground).ok());
+            colors.background = colors.background.or_else(|| RGBA::parse(*background).ok());
+            colors.palette = palette.iter()
+                .map(|color| RGBA::parse(*color).expect("built-in colors are valid"))
+                .collect();
+        }
+
+        let overrides: Vec<(usize, RGBA)> = (0..PALETTE_SIZE)
+            .filter_map(|i| color(&format!("color{}", i)).map(|c| (i, c)))
+            .collect();
+
+        if !overrides.is_empty() {
+            // without a theme the unset entries come from the linux palette
+            if colors.palette.is_empty() {
+                let (_, _, _, palette) = theme("linux").expect("the linux theme is built in");
+                colors.palette = palette.iter()
+                    .map(|color| RGBA::parse(*color).expect("built-in colors are valid"))
+                    .collect();
+            }
+            for (i, color) in overrides {
+                colors.palette[i] = color;
+            }
+        }
+
+        colors
+    }
+
+    pub fn foreground(&self) -> Option<&RGBA> {
+        self.foreground.as_ref()
+    }
+
+    pub fn background(&self) -> Option<&RGBA> {
+        self.background.as_ref()
+    }
+
+    pub fn apply(&self, terminal: &Terminal) {
+        let palette: Vec<&RGBA> = self.palette.iter().collect();
+        terminal.set_colors(self.foreground.as_ref(), self.background.as_ref(), &palette);
+        terminal.set_color_cursor(self.cursor.as_ref());
+        terminal.set_color_cursor_foreground(self.cursor_foreground.as_ref());
+        terminal.set_color_highlight(self.highlight.as_ref());
+        terminal.set_color_highlight_foreground(self.highlight_foreground.as_ref());
+        terminal.set_color_bold(self.bold.as_ref());
+    }
+}
@@ -0,0 +1,424 @@
+//
+// Settings shared by every window
+//
+// termint.ini and the styles file are watched for changes. When one of
+// them changes the settings are read again and re-applied to every open
+// terminal (colours, font, scrollback, keybindings and CSS). A file that
+// fails to parse keeps the previous settings.
+//
+// Terminals of a profile get the colours and font of their profile.
+//
+// What termint remembers on its own (the zoom) goes to state.ini next
+// to termint.ini, so the file of the user is never rewritten.
+//
+
+use std::{
+    cell::{Cell, Ref, RefCell},
+    collections::HashMap,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Duration,
+};
+
+use gtk4::{
+    gdk,
+    gio::{self, Cancellable, FileMonitor, FileMonitorEvent, FileMonitorFlags},
+    glib,
+    prelude::*,
+    Application,
+    CssProvider,
+    Label,
+    STYLE_PROVIDER_PRIORITY_APPLICATION,
+    ScrolledWindow,
+    Widget,
+};
+
+use ini::{Ini, Properties};
+
+use vte4::{
+    Terminal,
+    TerminalExt,
+};
+
+use crate::{
+    background::Background,
+    clipboard,
+    colors::Colors,
+    fonts::{self, Font},
+    keybindings,
+    panes,
+    profiles,
+    remote::Registry,
+    scrolling::Scrolling,
+    zoom,
+};
+
+// Editors write a file in several steps, wait for them to settle
+const RELOAD_DELAY: Duration = Duration::from_millis(200);
+
+// Changes made in a row (like Ctrl+scroll) are written once
+const SAVE_DELAY: Duration = Duration::from_millis(500);
+
+const STATE_FILE: &str = "state.ini";
+
+pub struct Config {
+    ini_file: PathBuf,
+    ini: RefCell<Ini>,
+    // replaced when styles.css is reloaded
+    css_provider: RefCell<CssProvider>,
+    // the background settings, above the styles file
+    background_provider: CssProvider,
+    styles_file: RefCell<Option<PathBuf>>,
+    colors: RefCell<Colors>,
+    font: RefCell<Font>,
+    scrolling: RefCell<Scrolling>,
+    background: RefCell<Background>,
+    // colours and font of the profiles in use, built when first needed
+    profiles: RefCell<HashMap<String, Rc<(Colors, Font)>>>,
+    // knows the profile of every terminal
+    terminals: Rc<Registry>,
+    // kept alive for as long as the files are watched
+    ini_monitor: RefCell<Option<FileMonitor>>,
+    styles_monitor: RefCell<Option<FileMonitor>>,
+    reload_pending: Cell<bool>,
+    state_file: PathBuf,
+    state: RefCell<Ini>,
+    save_pending: Cell<bool>,
+}
+
+fn settings_of(ini: &Ini) -> Properties {
+    ini.section(Some("Settings")).cloned().unwrap_or_default()
+}
+
+fn config_dir_of(ini_file: &Path) -> &Path {
+    ini_file.parent().unwrap_or(Path::new("."))
+}
+
+fn styles_file_of(ini: &Ini) -> Option<PathBuf> {
+    ini.section(Some("Settings"))
+        .and_then(|settings| settings.get("styles_file"))
+        .map(PathBuf::from)
+}
+
+// Loads a CSS file, failing on any parse error
+fn load_css(path: &Path) -> Result<CssProvider, String> {
+    let provider = CssProvider::new();
+    let errors = Rc::new(RefCell::new(Vec::new()));
+    let sink = errors.clone();
+    provider.connect_parsing_error(move |_, _, err| {
+        sink.borrow_mut().push(err.to_string());
+    });
+    provider.load_from_path(path);
+    let errors = errors.borrow();
+    if errors.is_empty() {
+        Ok(provider)
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+impl Config {
+
+    pub fn new(ini_file: PathBuf, ini: Ini, terminals: Rc<Registry>) -> Rc<Self> {
+        let settings = settings_of(&ini);
+        // any widget will do to look up the installed fonts
+        let context = Label::new(None).pango_context();
+
+        let background = Background::from_settings(&settings, config_dir_of(&ini_file));
+        let background_provider = CssProvider::new();
+        background_provider.load_from_data(&background.css());
+        if let Some(display) = gdk::Display::default() {
+            gtk4::style_context_add_provider_for_display(
+                &display,
+                &background_provider,
+                STYLE_PROVIDER_PRIORITY_APPLICATION + 1,
+            );
+        }
+
+        let state_file = ini_file.with_file_name(STATE_FILE);
+        let state = match Ini::load_from_file(&state_file) {
+            Ok(state) => state,
+            Err(ini::Error::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => Ini::new(),
+            Err(err) => {
+                eprintln!("Warning: ignoring {}: {}", state_file.display(), err);
+                Ini::new()
+            }
+        };
+
+        let config = Rc::new(Config {
+            css_provider: RefCell::new(CssProvider::new()),
+            background_provider,
+            styles_file: RefCell::new(styles_file_of(&ini)),
+            colors: RefCell::new(Colors::from_section(ini.section(Some("Colors")))),
+            font: RefCell::new(Font::from_settings(&settings, &context)),
+            scrolling: RefCell::new(Scrolling::from_settings(&settings)),
+            background: RefCell::new(background),
+            profiles: RefCell::new(HashMap::new()),
+            terminals,
+            ini: RefCell::new(ini),
+            ini_file,
+            ini_monitor: RefCell::new(None),
+            styles_monitor: RefCell::new(None),
+            reload_pending: Cell::new(false),
+            state_file,
+            state: RefCell::new(state),
+            save_pending: Cell::new(false),
+        });
+
+        match config.styles_file.borrow().as_deref() {
+            Some(styles_file) => {
+                config.css_provider.borrow().load_from_path(styles_file);
+            },
+            None => {
+                config.css_provider.borrow().load_from_data(crate::AppTerm::default_style().as_str());
+            }
+        };
+
+        config
+    }
+
+    pub fn ini(&self) -> Ref<'_, Ini> {
+        self.ini.borrow()
+    }
+
+    pub fn css_provider(&self) -> CssProvider {
+        self.css_provider.borrow().clone()
+    }
+
+    // The font scale of a terminal before it is zoomed
+    pub fn font_scale(&self, terminal: &Terminal) -> f64 {
+        match self.terminals.profile_of(terminal).and_then(|name| self.profile(&name)) {
+            Some(profile) => profile.1.scale(),
+            None => self.font.borrow().scale(),
+        }
+    }
+
+    // The [Colors] and [Settings] sections with a profile on top
+    fn profile(&self, name: &str) -> Option<Rc<(Colors, Font)>> {
+        if let Some(profile) = self.profiles.borrow().get(name) {
+            return Some(profile.clone());
+        }
+
+        let ini = self.ini.borrow();
+        let section = profiles::section(&ini, name)?;
+        let context = Label::new(None).pango_context();
+        let profile = Rc::new((
+            Colors::from_section(Some(&profiles::overlay(ini.section(Some("Colors")), section))),
+            Font::from_settings(&profiles::overlay(ini.section(Some("Settings")), section), &context),
+        ));
+        self.profiles.borrow_mut().insert(name.to_string(), profile.clone());
+        Some(profile)
+    }
+
+    // Applies the colours, font, scrolling and paste settings to a terminal
+    pub fn apply(&self, terminal: &Terminal) {
+        match self.terminals.profile_of(terminal).and_then(|name| self.profile(&name)) {
+            Some(profile) => {
+                profile.0.apply(terminal);
+                profile.1.apply(terminal);
+            }
+            None => {
+                self.colors.borrow().apply(terminal);
+                self.font.borrow().apply(terminal);
+            }
+        }
+        self.scrolling.borrow().apply(terminal);
+        self.apply_background(terminal);
+
+        if let Some(stream) = self.terminals.stream_of(terminal) {
+            clipboard::follow_modes(&stream, &self.ini.borrow());
+        }
+
+        if let Some(zoom) = zoom::saved(&self.ini.borrow(), &self.state.borrow()) {
+            let scale = terminal.font_scale() * zoom;
+            terminal.set_font_scale(scale.clamp(fonts::MIN_FONT_SCALE, fonts::MAX_FONT_SCALE));
+        }
+    }
+
+    // Remembers a value in state.ini, written a moment later
+    pub fn save_state(self: &Rc<Self>, key: &str, value: String) {
+        self.state.borrow_mut().with_general_section().set(key, value);
+        if self.save_pending.replace(true) {
+            return;
+        }
+
+        let config = Rc::downgrade(self);
+        glib::timeout_add_local_once(SAVE_DELAY, move || {
+            if let Some(config) = config.upgrade() {
+                config.save_pending.set(false);
+                config.save_state_file();
+            }
+        });
+    }
+
+    fn save_state_file(&self) {
+        if let Some(dir) = self.state_file.parent() {
+            if let Err(err) = std::fs::create_dir_all(dir) {
+                eprintln!("Failed to create {}: {}", dir.display(), err);
+                return;
+            }
+        }
+        if let Err(err) = self.state.borrow().write_to_file(&self.state_file) {
+            eprintln!("Failed to save {}: {}", self.state_file.display(), err);
+        }
+    }
+
+    // The colours of a terminal, from its profile or [Colors]
+    pub fn colors_of(&self, terminal: &Terminal) -> Colors {
+        match self.terminals.profile_of(terminal).and_then(|name| self.profile(&name)) {
+            Some(profile) => profile.0.clone(),
+            None => self.colors.borrow().clone(),
+        }
+    }
+
+    // Sets the background opacity of a terminal, which depends on its window
+    pub fn apply_background(&self, terminal: &Terminal) {
+        self.background.borrow().apply(terminal, self.colors_of(terminal).background());
+    }
+
+    pub fn apply_to_pane(&self, scrolled_window: &ScrolledWindow) {
+        self.scrolling.borrow().apply_to_pane(scrolled_window);
+    }
+
+    // Starts watching termint.ini and the styles file
+    pub fn watch(self: &Rc<Self>, app: &Application) {
+        self.ini_monitor.replace(self.monitor(app, &self.ini_file));
+        self.watch_styles(app);
+    }
+
+    fn watch_styles(self: &Rc<Self>, app: &Application) {
+        let monitor = self.styles_file.borrow()
+            .as_deref()
+            .and_then(|path| self.monitor(app, path));
+        self.styles_monitor.replace(monitor);
+    }
+
+    fn monitor(self: &Rc<Self>, app: &Application, path: &Path) -> Option<FileMonitor> {
+        let monitor = match gio::File::for_path(path)
+            .monitor_file(FileMonitorFlags::WATCH_MOVES, None::<&Cancellable>) {
+            Ok(monitor) => monitor,
+            Err(err) => {
+                eprintln!("Warning: unable to watch {}: {}", path.display(), err);
+                return None;
+            }
+        };
+
+        let config = Rc::downgrade(self);
+        let app = app.downgrade();
+        monitor.connect_changed(move |_, _, _, event| {
+            if matches!(event, FileMonitorEvent::Deleted | FileMonitorEvent::MovedOut) {
+                return;
+            }
+            if let (Some(config), Some(app)) = (config.upgrade(), app.upgrade()) {
+                config.schedule_reload(&app);
+            }
+        });
+
+        Some(monitor)
+    }
+
+    fn schedule_reload(self: &Rc<Self>, app: &Application) {
+        if self.reload_pending.replace(true) {
+            return;
+        }
+        let config = Rc::downgrade(self);
+        let app = app.downgrade();
+        glib::timeout_add_local_once(RELOAD_DELAY, move || {
+            if let (Some(config), Some(app)) = (config.upgrade(), app.upgrade()) {
+                config.reload_pending.set(false);
+                config.reload(&app);
+            }
+        });
+    }
+
+    // Reads both files again and re-applies them to every open terminal
+    fn reload(self: &Rc<Self>, app: &Application) {
+        let ini = match Ini::load_from_file(&self.ini_file) {
+            Ok(ini) => ini,
+            Err(err) => {
+                eprintln!("failed to parse config file, keeping the previous settings: {}", err);
+                return;
+            }
+        };
+
+        // the zoom and the font scale each terminal has now
+        let terminals: Vec<(Terminal, f64, f64)> = app.windows()
+            .iter()
+            .flat_map(|window| panes::terminals(window.upcast_ref()))
+            .map(|terminal| {
+                let zoom = terminal.font_scale();
+                let scale = self.font_scale(&terminal);
+                (terminal, zoom, scale)
+            })
+            .collect();
+
+        let settings = settings_of(&ini);
+        let context = Label::new(None).pango_context();
+
+        self.colors.replace(Colors::from_section(ini.section(Some("Colors"))));
+        self.font.replace(Font::from_settings(&settings, &context));
+        self.scrolling.replace(Scrolling::from_settings(&settings));
+        self.background.replace(Background::from_settings(&settings, config_dir_of(&self.ini_file)));
+        self.background_provider.load_from_data(&self.background.borrow().css());
+
+        keybindings::apply(app, ini.section(Some("Keybindings")));
+
+        let styles_file = styles_file_of(&ini);
+        let styles_moved = *self.styles_file.borrow() != styles_file;
+        self.styles_file.replace(styles_file);
+        self.ini.replace(ini);
+        self.profiles.borrow_mut().clear();
+
+        let old_provider = self.reload_styles();
+        if styles_moved {
+            self.watch_styles(app);
+        }
+
+        for (terminal, zoom, old_scale) in terminals {
+            self.apply(&terminal);
+            // a zoomed terminal keeps its zoom unless font_scale changed
+            if old_scale == self.font_scale(&terminal) {
+                terminal.set_font_scale(zoom);
+            }
+            let pane = panes::pane_of(&terminal).and_downcast::<ScrolledWindow>();
+            if let Some(pane) = &pane {
+                self.apply_to_pane(pane);
+            }
+            if let Some(old_provider) = &old_provider {
+                self.replace_provider(terminal.upcast_ref(), old_provider);
+                if let Some(pane) = &pane {
+                    self.replace_provider(pane.upcast_ref(), old_provider);
+                }
+            }
+        }
+    }
+
+    fn replace_provider(&self, widget: &Widget, old_provider: &CssProvider) {
+        let style_context = widget.style_context();
+        style_context.remove_provider(old_provider);
+        style_context.add_provider(&self.css_provider(), STYLE_PROVIDER_PRIORITY_APPLICATION);
+    }
+
+    // Parses the styles into a new provider, which takes the place of the
+    // old one only if there was no error; returns the old one
+    fn reload_styles(&self) -> Option<CssProvider> {
+        let provider = match self.styles_file.borrow().as_deref() {
+            Some(styles_file) => match load_css(styles_file) {
+                Ok(provider) => provider,
+                Err(err) => {
+                    eprintln!(
+                        "failed to parse {}, keeping the previous styles: {}",
+                        styles_file.display(), err,
+                    );
+                    return None;
+                }
+            },
+            None => {
+                let provider = CssProvider::new();
+                provider.load_from_data(crate::AppTerm::default_style().as_str());
+                provider
+            }
+        };
+        Some(self.css_provider.replace(provider))
+    }
+}
@@ -0,0 +1,153 @@
+//
+// Right-click menu
+//
+// Extra items come from the [ContextMenu] section of termint.ini, in
+// order, as "label = action". The action is a name from [Keybindings]
+// or "run:<command>" to run a command in a new tab:
+//
+// [ContextMenu]
+// Split Right = split-right
+// Top = run:htop
+//
+
+use std::rc::Rc;
+
+use gtk4::{
+    gdk,
+    gio::{self, SimpleAction},
+    glib,
+    prelude::*,
+    ApplicationWindow,
+    EventSequenceState,
+    GestureClick,
+    PopoverMenu,
+};
+
+use ini::Properties;
+
+use vte4::Terminal;
+
+use crate::{config::Config, keybindings, links};
+
+const RUN_PREFIX: &str = "run:";
+
+// Adds the actions of the link items, which take the URI as parameter
+pub fn add_actions(window: &ApplicationWindow) {
+    let copy_link = SimpleAction::new("copy-link", Some(glib::VariantTy::STRING));
+    let window_weak = window.downgrade();
+    copy_link.connect_activate(move |_, uri| {
+        if let (Some(window), Some(uri)) = (window_weak.upgrade(), uri.and_then(|v| v.str())) {
+            window.clipboard().set_text(uri);
+        }
+    });
+    window.add_action(&copy_link);
+
+    let open_link = SimpleAction::new("open-link", Some(glib::VariantTy::STRING));
+    let window_weak = window.downgrade();
+    open_link.connect_activate(move |_, uri| {
+        if let (Some(window), Some(uri)) = (window_weak.upgrade(), uri.and_then(|v| v.str())) {
+            links::open(&window, uri);
+        }
+    });
+    window.add_action(&open_link);
+}
+
+// Opens the menu on a secondary click
+pub fn setup(terminal: &Terminal, config: &Rc<Config>) {
+    let gesture = GestureClick::new();
+    gesture.set_button(gdk::BUTTON_SECONDARY);
+
+    let config = config.clone();
+    gesture.connect_pressed(move |gesture, _, x, y| {
+        let Ok(terminal) = gesture.widget().downcast::<Terminal>() else {
+            return;
+        };
+        gesture.set_state(EventSequenceState::Claimed);
+        // the window actions run on the focused terminal
+        terminal.grab_focus();
+
+        let menu = {
+            let ini = config.ini();
+            build(links::uri_at(&terminal, x, y), ini.section(Some("ContextMenu")))
+        };
+
+        let popover = PopoverMenu::from_model(Some(&menu));
+        popover.set_parent(&terminal);
+        popover.set_has_arrow(false);
+        popover.set_halign(gtk4::Align::Start);
+        popover.set_pointing_to(Some(&gdk::Rectangle::new(x as i32, y as i32, 1, 1)));
+        // the menu is rebuilt on every click, drop it once it is closed
+        popover.connect_closed(|popover| {
+            let popover = popover.clone();
+            glib::idle_add_local_once(move || popover.unparent());
+        });
+        popover.popup();
+    });
+    terminal.add_controller(gesture);
+}
+
+fn build(uri: Option<String>, custom: Option<&Properties>) -> gio::Menu {
+    let menu = gio::Menu::new();
+
+    let clipboard = gio::Menu::new();
+    clipboard.append(Some("_Copy"), Some("win.copy"));
+    clipboard.append(Some("_Paste"), Some("win.paste"));
+    menu.append_section(None, &clipboard);
+
+    if let Some(uri) = uri {
+        let link = gio::Menu::new();
+        for (label, action) in [("Copy _Link", "win.copy-link"), ("_Open Link", "win.open-link")] {
+            let item = gio::MenuItem::new(Some(label), None);
+            item.set_action_and_target_value(Some(action), Some(&uri.to_variant()));
+            link.append_item(&item);
+        }
+        menu.append_section(None, &link);
+    }
+
+    let edit = gio::Menu::new();
+    edit.append(Some("Select _All"), Some("win.select-all"));
+    edit.append(Some("_Search…"), Some("win.search"));
+    menu.append_section(None, &edit);
+
+    let terminal = gio::Menu::new();
+    terminal.append(Some("_Reset"), Some("win.reset"));
+    terminal.append(Some("Clear Scroll_back"), Some("win.clear-scrollback"));
+    terminal.append(Some("_Export Scrollback…"), Some("win.export-scrollback"));
+    menu.append_section(None, &terminal);
+
+    let app = gio::Menu::new();
+    app.append(Some("New _Window"), Some("app.new-window"));
+    app.append(Some("P_references"), Some("app.preferences"));
+    menu.append_section(None, &app);
+
+    if let Some(custom) = custom {
+        let items = gio::Menu::new();
+        for (label, action) in custom.iter() {
+            if let Some(item) = item(label, action.trim()) {
+                items.append_item(&item);
+            }
+        }
+        if items.n_items() > 0 {
+            menu.append_section(None, &items);
+        }
+    }
+
+    menu
+}
+
+// A menu item of the [ContextMenu] section
+fn item(label: &str, action: &str) -> Option<gio::MenuItem> {
+    if let Some(command) = action.strip_prefix(RUN_PREFIX) {
+        let item = gio::MenuItem::new(Some(label), None);
+        item.set_action_and_target_value(Some("win.run"), Some(&command.trim().to_variant()));
+        return Some(item);
+    }
+
+    match keybindings::action_of(action) {
+        Some(action) => Some(gio::MenuItem::new(Some(label), Some(action))),
+        None => {
+            eprintln!("Warning: invalid value \"{}\" for \"{}\"", action, label);
+            None
+        }
+    }
+}
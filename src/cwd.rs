@@ -0,0 +1,104 @@
+//
+// Current directory of the shell
+//
+// A shell that reports its directory with the OSC 7 escape sequence
+// lets new windows, tabs and splits open where the focused terminal is.
+// `termint --shell-integration <shell>` prints a snippet that sends it
+// before every prompt, which also tells notify.rs that a command ended:
+//
+// eval "$(termint --shell-integration bash)"     (in ~/.bashrc)
+// eval "$(termint --shell-integration zsh)"      (in ~/.zshrc)
+// termint --shell-integration fish | source      (in config.fish)
+//
+// The directory is also available to title_template in [Settings]:
+//
+// title_template = {title}    ({title}, {cwd} and {dir} are replaced)
+//
+
+use gtk4::gio::{self, prelude::*};
+
+use vte4::{
+    Terminal,
+    TerminalExt,
+};
+
+use crate::APP_TITLE;
+
+pub const SHELLS: [&str; 3] = ["bash", "zsh", "fish"];
+
+pub const DEFAULT_TITLE_TEMPLATE: &str = "{title}";
+
+const BASH: &str = r#"__termint_osc7() {
+    local LC_ALL=C i c out=""
+    for ((i = 0; i < ${#PWD}; i++)); do
+        c=${PWD:i:1}
+        case $c in
+            [-/:_.~[:alnum:]]) out+=$c ;;
+            *) printf -v c '%%%02X' "'$c"; out+=$c ;;
+        esac
+    done
+    printf '\e]7;file://%s%s\e\\' "$HOSTNAME" "$out"
+}
+PROMPT_COMMAND="__termint_osc7${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+"#;
+
+const ZSH: &str = r#"__termint_osc7() {
+    local LC_ALL=C i c out=""
+    for ((i = 1; i <= ${#PWD}; i++)); do
+        c=${PWD[i]}
+        case $c in
+            [-/:_.~[:alnum:]]) out+=$c ;;
+            *) out+=$(printf '%%%02X' "'$c") ;;
+        esac
+    done
+    printf '\e]7;file://%s%s\e\\' "$HOST" "$out"
+}
+autoload -Uz add-zsh-hook
+add-zsh-hook precmd __termint_osc7
+"#;
+
+const FISH: &str = r#"function __termint_osc7 --on-event fish_prompt
+    printf '\e]7;file://%s%s\e\\' (hostname) (string escape --style=url -- $PWD)
+end
+"#;
+
+// The snippet that makes a shell report its directory
+pub fn shell_integration(shell: &str) -> Option<&'static str> {
+    match shell {
+        "bash" => Some(BASH),
+        "zsh" => Some(ZSH),
+        "fish" => Some(FISH),
+        _ => None,
+    }
+}
+
+// The directory the shell of a terminal reported, if it is a local one
+pub fn of(terminal: &Terminal) -> Option<String> {
+    let uri = terminal.current_directory_uri()?;
+    let path = gio::File::for_uri(&uri).path()?;
+    path.is_dir().then(|| path.to_string_lossy().to_string())
+}
+
+// Fills in a title template for a terminal
+pub fn title(template: &str, terminal: &Terminal) -> String {
+    let window_title = terminal.window_title()
+        .map(|title| title.to_string())
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| APP_TITLE.to_string());
+    let cwd = of(terminal).unwrap_or_default();
+    let dir = match cwd.as_str() {
+        "/" => "/".to_string(),
+        cwd => cwd.rsplit('/').next().unwrap_or_default().to_string(),
+    };
+
+    let title = template
+        .replace("{title}", &window_title)
+        .replace("{cwd}", &cwd)
+        .replace("{dir}", &dir);
+
+    if title.trim().is_empty() {
+        APP_TITLE.to_string()
+    } else {
+        title
+    }
+}
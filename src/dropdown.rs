@@ -0,0 +1,348 @@
+//
+// Drop-down (Quake-style) window
+//
+// With --dropdown the first instance keeps a single undecorated window
+// as wide as the monitor that is shown and hidden with `termint --toggle`
+// or the app.toggle-dropdown action. Read from the [Settings] section of
+// termint.ini:
+//
+// dropdown_height = 40                  (percentage of the monitor height)
+// dropdown_hide_on_focus_loss = false   (hide when another window is focused)
+// dropdown_slide_time = 200             (milliseconds, 0 to show at once)
+//
+// GTK 4 cannot move windows. Built with the layer-shell feature, the
+// window is a layer surface anchored at the top of the output the
+// compositor picks (wlroots compositors, KDE); built with the x11
+// feature, it is moved to the top of the monitor under the pointer on
+// X11. Both slide it in and out by moving it above the top edge. Other
+// desktops decide on their own where the window goes, without sliding;
+// most put an undecorated window of the monitor width at the top.
+//
+
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    time::Duration,
+};
+
+use gtk4::{
+    gdk,
+    glib,
+    prelude::*,
+    Application,
+    ApplicationWindow,
+    TickCallbackId,
+};
+
+#[cfg(feature = "layer-shell")]
+use gtk4_layer_shell::{Edge, KeyboardMode, Layer, LayerShell};
+
+#[cfg(feature = "x11")]
+use gdk4_x11::{X11Display, X11Surface};
+
+use ini::Properties;
+
+use crate::{config::Config, get_bool, DEFAULT_HEIGHT, DEFAULT_WIDTH};
+
+const DEFAULT_HEIGHT_PERCENT: f64 = 40.0;
+const DEFAULT_SLIDE_TIME: u64 = 200;
+
+// The app ID used by --dropdown and --toggle without --app-id, so that
+// `termint --toggle` always finds the drop-down instance
+pub const APP_ID: &str = "TermintDropdown";
+
+#[derive(Debug, Clone)]
+pub struct Dropdown {
+    height_percent: f64,
+    slide_time: Duration,
+}
+
+impl Dropdown {
+
+    pub fn default_settings(settings: &mut ini::SectionSetter) {
+        settings
+            .set("dropdown_height", DEFAULT_HEIGHT_PERCENT.to_string())
+            .set("dropdown_hide_on_focus_loss", "false")
+            .set("dropdown_slide_time", DEFAULT_SLIDE_TIME.to_string());
+    }
+
+    pub fn from_settings(settings: &Properties) -> Self {
+        let height_percent = match settings.get("dropdown_height").map(str::trim) {
+            Some(value) => match value.trim_end_matches('%').parse::<f64>() {
+                Ok(percent) if percent > 0.0 && percent <= 100.0 => percent,
+                _ => {
+                    eprintln!("Warning: invalid value \"{}\" for \"dropdown_height\"", value);
+                    DEFAULT_HEIGHT_PERCENT
+                }
+            },
+            None => DEFAULT_HEIGHT_PERCENT,
+        };
+
+        let slide_time = match settings.get("dropdown_slide_time").map(str::trim) {
+            Some(value) => value.parse::<u64>().unwrap_or_else(|_| {
+                eprintln!("Warning: invalid value \"{}\" for \"dropdown_slide_time\"", value);
+                DEFAULT_SLIDE_TIME
+            }),
+            None => DEFAULT_SLIDE_TIME,
+        };
+
+        Dropdown {
+            height_percent,
+            slide_time: Duration::from_millis(slide_time),
+        }
+    }
+
+    // The window size on the monitor of the active window, or on the
+    // first monitor
+    pub fn size(&self, app: &Application) -> (usize, usize) {
+        let Some(monitor) = current_monitor(app) else {
+            return (DEFAULT_WIDTH, DEFAULT_HEIGHT);
+        };
+        let geometry = monitor.geometry();
+        (
+            geometry.width() as usize,
+            (geometry.height() as f64 * self.height_percent / 100.0) as usize,
+        )
+    }
+}
+
+// The monitor under the pointer, on X11
+#[cfg(feature = "x11")]
+fn pointer_monitor(display: &gdk::Display) -> Option<gdk::Monitor> {
+    let display = display.downcast_ref::<X11Display>()?;
+    let (mut root, mut child) = (0, 0);
+    let (mut x, mut y, mut window_x, mut window_y, mut mask) = (0, 0, 0, 0, 0);
+    let found = unsafe {
+        x11::xlib::XQueryPointer(
+            display.xdisplay(),
+            display.xrootwindow(),
+            &mut root,
+            &mut child,
+            &mut x,
+            &mut y,
+            &mut window_x,
+            &mut window_y,
+            &mut mask,
+        )
+    };
+    if found == 0 {
+        return None;
+    }
+    display.monitors()
+        .iter::<gdk::Monitor>()
+        .filter_map(Result::ok)
+        .find(|monitor| {
+            // the X coordinates are in device pixels
+            let scale = monitor.scale_factor().max(1);
+            monitor.geometry().contains_point(x / scale, y / scale)
+        })
+}
+
+fn current_monitor(app: &Application) -> Option<gdk::Monitor> {
+    let display = gdk::Display::default()?;
+    #[cfg(feature = "x11")]
+    if let Some(monitor) = pointer_monitor(&display) {
+        return Some(monitor);
+    }
+    app.active_window()
+        .and_then(|window| window.surface())
+        .and_then(|surface| display.monitor_at_surface(&surface))
+        .or_else(|| display.monitors().item(0).and_downcast::<gdk::Monitor>())
+}
+
+// Puts the window `offset` pixels above the top of `monitor`
+fn place(window: &ApplicationWindow, monitor: &gdk::Monitor, offset: i32) {
+    #[cfg(feature = "layer-shell")]
+    if window.is_layer_window() {
+        window.set_margin(Edge::Top, -offset);
+        return;
+    }
+    #[cfg(feature = "x11")]
+    if let Some(surface) = window.surface().and_downcast::<X11Surface>() {
+        let Some(display) = surface.display().downcast::<X11Display>().ok() else {
+            return;
+        };
+        let geometry = monitor.geometry();
+        let scale = surface.scale_factor();
+        unsafe {
+            x11::xlib::XMoveWindow(
+                display.xdisplay(),
+                surface.xid(),
+                geometry.x() * scale,
+                (geometry.y() - offset) * scale,
+            );
+        }
+        return;
+    }
+    // the compositor places the window
+    let _ = (window, monitor, offset);
+}
+
+// Whether the window can be placed and slid
+fn can_move(window: &ApplicationWindow) -> bool {
+    #[cfg(feature = "layer-shell")]
+    if window.is_layer_window() {
+        return true;
+    }
+    #[cfg(feature = "x11")]
+    if WidgetExt::display(window).is::<X11Display>() {
+        return true;
+    }
+    let _ = window;
+    false
+}
+
+// The slide of the drop-down window in progress
+#[derive(Default)]
+pub struct Slide {
+    tick: RefCell<Option<TickCallbackId>>,
+    // how far the window is above the top of the monitor
+    offset: Cell<i32>,
+}
+
+impl Slide {
+
+    fn stop(&self) {
+        if let Some(tick) = self.tick.take() {
+            tick.remove();
+        }
+    }
+
+    fn place(&self, window: &ApplicationWindow, monitor: &gdk::Monitor, offset: i32) {
+        self.offset.set(offset);
+        place(window, monitor, offset);
+    }
+
+    // Moves the window on `monitor` from where it is to `to` pixels above
+    // its top, then calls `done`
+    fn run(
+        self: &Rc<Self>,
+        window: &ApplicationWindow,
+        monitor: gdk::Monitor,
+        to: i32,
+        time: Duration,
+        done: impl Fn(&ApplicationWindow) + 'static,
+    ) {
+        self.stop();
+        let from = self.offset.get();
+
+        let slide = Rc::downgrade(self);
+        let start: RefCell<Option<i64>> = RefCell::new(None);
+        let time = time.as_micros().max(1) as f64;
+        let tick = window.add_tick_callback(move |widget, clock| {
+            let Some(window) = widget.downcast_ref::<ApplicationWindow>() else {
+                return glib::ControlFlow::Break;
+            };
+            let now = clock.frame_time();
+            let start = *start.borrow_mut().get_or_insert(now);
+            let progress = ((now - start) as f64 / time).min(1.0);
+            // ease out, fast at first and slowing down at the end
+            let eased = 1.0 - (1.0 - progress).powi(3);
+            let Some(slide) = slide.upgrade() else {
+                return glib::ControlFlow::Break;
+            };
+            slide.place(window, &monitor, from + ((to - from) as f64 * eased).round() as i32);
+            if progress < 1.0 {
+                return glib::ControlFlow::Continue;
+            }
+            slide.tick.take();
+            done(window);
+            glib::ControlFlow::Break
+        });
+        self.tick.replace(Some(tick));
+    }
+}
+
+// Turns a new window into the drop-down window, before it is shown
+pub fn setup(window: &ApplicationWindow, config: &Rc<Config>, slide: &Rc<Slide>) {
+    window.set_decorated(false);
+    // the size follows the monitor, not the minimum of a normal window
+    window.set_size_request(-1, -1);
+
+    #[cfg(feature = "layer-shell")]
+    if gtk4_layer_shell::is_supported() {
+        window.init_layer_shell();
+        window.set_namespace("termint-dropdown");
+        window.set_layer(Layer::Top);
+        window.set_keyboard_mode(KeyboardMode::OnDemand);
+        for edge in [Edge::Top, Edge::Left, Edge::Right] {
+            window.set_anchor(edge, true);
+        }
+    }
+
+    // slide in whenever the window is shown
+    let config_weak = Rc::downgrade(config);
+    let slide_weak = Rc::downgrade(slide);
+    window.connect_map(move |window| {
+        let (Some(config), Some(slide)) = (config_weak.upgrade(), slide_weak.upgrade()) else {
+            return;
+        };
+        slide_in(window, &config, &slide);
+    });
+
+    let config = Rc::downgrade(config);
+    let slide = Rc::downgrade(slide);
+    window.connect_is_active_notify(move |window| {
+        if window.is_active() || !window.is_visible() {
+            return;
+        }
+        let (Some(config), Some(slide)) = (config.upgrade(), slide.upgrade()) else {
+            return;
+        };
+        let hide_on_focus_loss = config.ini()
+            .section(Some("Settings"))
+            .map(|settings| get_bool(settings, "dropdown_hide_on_focus_loss", false))
+            .unwrap_or(false);
+        if hide_on_focus_loss {
+            hide(window, &config, &slide);
+        }
+    });
+}
+
+fn slide_time(config: &Config) -> Duration {
+    let settings = config.ini().section(Some("Settings")).cloned().unwrap_or_default();
+    Dropdown::from_settings(&settings).slide_time
+}
+
+fn slide_in(window: &ApplicationWindow, config: &Config, slide: &Rc<Slide>) {
+    let Some(monitor) = window.application().and_then(|app| current_monitor(&app)) else {
+        return;
+    };
+    if !can_move(window) {
+        return;
+    }
+    let time = slide_time(config);
+    if time.is_zero() {
+        slide.stop();
+        slide.place(window, &monitor, 0);
+    } else {
+        // from just above the top edge
+        slide.stop();
+        slide.place(window, &monitor, window.default_height());
+        slide.run(window, monitor, 0, time, |_| {});
+    }
+}
+
+// Shows the drop-down window again, resized for the current monitor
+pub fn show(window: &ApplicationWindow, app: &Application, config: &Config) {
+    let settings = config.ini().section(Some("Settings")).cloned().unwrap_or_default();
+    let (width, height) = Dropdown::from_settings(&settings).size(app);
+    window.set_default_size(width as i32, height as i32);
+    window.present();
+}
+
+// Slides the drop-down window out and hides it
+pub fn hide(window: &ApplicationWindow, config: &Config, slide: &Rc<Slide>) {
+    let time = slide_time(config);
+    let monitor = window.surface()
+        .and_then(|surface| surface.display().monitor_at_surface(&surface));
+    match monitor {
+        Some(monitor) if can_move(window) && !time.is_zero() => {
+            slide.run(window, monitor, window.height(), time, |window| window.set_visible(false));
+        }
+        _ => {
+            slide.stop();
+            window.set_visible(false);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//
+// Environment of the child process
+//
+// The child inherits the environment of termint, then gets the
+// variables that identify the terminal, then the [Environment] section
+// of termint.ini and last the --env KEY=VALUE options. With a clean
+// environment only a few essential variables are inherited.
+//
+
+use std::env;
+
+use gtk4::glib::SpawnFlags;
+
+use ini::Properties;
+
+use crate::{APP_NAME, VERSION};
+
+// Tells VTE not to merge the environment of termint (VTE_SPAWN_NO_PARENT_ENVV)
+const VTE_SPAWN_NO_PARENT_ENVV: u32 = 1 << 25;
+
+// Inherited even with a clean environment, the shell is of little use
+// without them
+const ESSENTIAL: &[&str] = &[
+    "HOME",
+    "USER",
+    "LOGNAME",
+    "SHELL",
+    "PATH",
+    "LANG",
+    "DISPLAY",
+    "WAYLAND_DISPLAY",
+    "XDG_RUNTIME_DIR",
+    "DBUS_SESSION_BUS_ADDRESS",
+];
+
+// Variables that let programs detect termint
+fn terminal_vars() -> Vec<(String, String)> {
+    vec![
+        ("TERM".to_string(), "xterm-256color".to_string()),
+        ("COLORTERM".to_string(), "truecolor".to_string()),
+        ("TERM_PROGRAM".to_string(), APP_NAME.to_string()),
+        ("TERM_PROGRAM_VERSION".to_string(), VERSION.to_string()),
+    ]
+}
+
+// Parses a KEY=VALUE pair
+pub fn parse_var(var: &str) -> Option<(String, String)> {
+    let (key, value) = var.split_once('=')?;
+    let key = key.trim();
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((key.to_string(), value.to_string()))
+}
+
+// The KEY=VALUE list passed to the child, later entries win
+pub fn child_env(
+    section: Option<&Properties>,
+    extra: &[(String, String)],
+    clean: bool,
+) -> Vec<String> {
+    let mut vars: Vec<(String, String)> = Vec::new();
+
+    if clean {
+        for key in ESSENTIAL {
+            if let Ok(value) = env::var(key) {
+                vars.push((key.to_string(), value));
+            }
+        }
+    }
+
+    vars.extend(terminal_vars());
+
+    if let Some(section) = section {
+        vars.extend(section.iter().map(|(key, value)| (key.to_string(), value.to_string())));
+    }
+
+    vars.extend(extra.iter().cloned());
+
+    // keep the last value of every variable
+    let mut merged: Vec<(String, String)> = Vec::new();
+    for (key, value) in vars {
+        match merged.iter_mut().find(|(k, _)| *k == key) {
+            Some(var) => var.1 = value,
+            None => merged.push((key, value)),
+        }
+    }
+
+    merged.into_iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect()
+}
+
+pub fn spawn_flags(clean: bool) -> SpawnFlags {
+    if clean {
+        SpawnFlags::SEARCH_PATH | SpawnFlags::from_bits_retain(VTE_SPAWN_NO_PARENT_ENVV)
+    } else {
+        SpawnFlags::SEARCH_PATH
+    }
+}
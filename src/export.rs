@@ -0,0 +1,189 @@
+//
+// Scrollback export
+//
+// The win.export-scrollback action saves the whole buffer of the focused
+// terminal (the scrollback and the screen) to a file picked in a dialog,
+// and `--dump-on-exit FILE` saves it when the command of the window
+// exits. Files ending in .html or .htm get the text with its colours,
+// any other file plain text.
+//
+
+use std::{
+    os::raw::c_long,
+    path::Path,
+    rc::Rc,
+};
+
+#[cfg(not(feature = "v4_10"))]
+use std::cell::{Cell, RefCell};
+
+use gtk4::{
+    gio::{self, prelude::*, Cancellable, FileCreateFlags},
+    glib,
+    prelude::*,
+    FileFilter,
+    Window,
+};
+
+#[cfg(feature = "v4_10")]
+use gtk4::FileDialog;
+
+#[cfg(not(feature = "v4_10"))]
+use gtk4::{FileChooserAction, FileChooserNative, ResponseType};
+
+use vte4::{
+    Format,
+    Terminal,
+    TerminalExt,
+    WriteFlags,
+};
+
+use crate::config::Config;
+
+fn is_html(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("html") || extension.eq_ignore_ascii_case("htm"))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// The first and last rows of the buffer
+fn rows(terminal: &Terminal) -> (c_long, c_long) {
+    let Some(adj) = terminal.vadjustment() else {
+        return (0, terminal.row_count() - 1);
+    };
+    let unit = if terminal.is_scroll_unit_is_pixels() {
+        terminal.char_height().max(1) as f64
+    } else {
+        1.0
+    };
+    ((adj.lower() / unit) as c_long, (adj.upper() / unit) as c_long - 1)
+}
+
+// The buffer as an HTML page in the colours of the terminal
+fn html(terminal: &Terminal, config: &Config, title: &str) -> String {
+    let (first, last) = rows(terminal);
+    let (text, _) = terminal.text_range_format(Format::Html, first, 0, last, terminal.column_count());
+
+    let colors = config.colors_of(terminal);
+    let mut style = String::new();
+    if let Some(color) = colors.foreground() {
+        style.push_str(&format!("color: {}; ", color.to_str()));
+    }
+    if let Some(color) = colors.background() {
+        style.push_str(&format!("background-color: {}; ", color.to_str()));
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body style=\"{}\">\n{}\n</body>\n</html>\n",
+        escape(title),
+        style.trim_end(),
+        text.unwrap_or_default(),
+    )
+}
+
+// Writes the buffer of a terminal to `path`
+pub fn write(terminal: &Terminal, config: &Config, path: &Path) -> Result<(), String> {
+    let file = gio::File::for_path(path);
+    let stream = file.replace(None, false, FileCreateFlags::NONE, None::<&Cancellable>)
+        .map_err(|err| format!("{}: {}", path.display(), err))?;
+
+    let result = if is_html(path) {
+        let title = path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        stream.write_all(html(terminal, config, &title).as_bytes(), None::<&Cancellable>)
+            .map(|_| ())
+    } else {
+        terminal.write_contents_sync(&stream, WriteFlags::Default, None::<&Cancellable>)
+    };
+
+    result
+        .and_then(|_| stream.close(None::<&Cancellable>))
+        .map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+fn filter(name: &str, suffixes: &[&str]) -> FileFilter {
+    let filter = FileFilter::new();
+    filter.set_name(Some(name));
+    for suffix in suffixes {
+        filter.add_pattern(&format!("*.{}", suffix));
+    }
+    filter
+}
+
+// Asks for a file to save to, with the file dialog of GTK 4.10
+#[cfg(feature = "v4_10")]
+fn choose_file(parent: Option<&Window>, name: &str, save: impl FnOnce(gio::File) + 'static) {
+    let filters = gio::ListStore::new::<FileFilter>();
+    filters.append(&filter("Text", &["txt", "log"]));
+    filters.append(&filter("HTML", &["html", "htm"]));
+
+    let dialog = FileDialog::builder()
+        .title("Export Scrollback")
+        .modal(true)
+        .initial_name(name)
+        .filters(&filters)
+        .build();
+    dialog.save(parent, None::<&Cancellable>, move |file| {
+        if let Ok(file) = file {
+            save(file);
+        }
+    });
+}
+
+// Asks for a file to save to, with the native file chooser
+#[cfg(not(feature = "v4_10"))]
+fn choose_file(parent: Option<&Window>, name: &str, save: impl FnOnce(gio::File) + 'static) {
+    let dialog = FileChooserNative::new(
+        Some("Export Scrollback"),
+        parent,
+        FileChooserAction::Save,
+        None,
+        None,
+    );
+    dialog.set_modal(true);
+    dialog.set_current_name(name);
+    dialog.add_filter(&filter("Text", &["txt", "log"]));
+    dialog.add_filter(&filter("HTML", &["html", "htm"]));
+
+    // GTK does not keep a native dialog alive, it is dropped once answered
+    let kept = RefCell::new(Some(dialog.clone()));
+    let save = Cell::new(Some(save));
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            if let (Some(file), Some(save)) = (dialog.file(), save.take()) {
+                save(file);
+            }
+        }
+        kept.take();
+    });
+    dialog.show();
+}
+
+// Asks where to save the buffer of a terminal and saves it
+pub fn ask(terminal: &Terminal, config: &Rc<Config>) {
+    let name = glib::DateTime::now_local()
+        .and_then(|now| now.format("termint-%Y%m%d-%H%M%S.txt"))
+        .map(|name| name.to_string())
+        .unwrap_or_else(|_| "termint.txt".to_string());
+
+    let parent = terminal.root().and_downcast::<Window>();
+    let terminal = terminal.downgrade();
+    let config = config.clone();
+    choose_file(parent.as_ref(), &name, move |file| {
+        let Some(terminal) = terminal.upgrade() else {
+            return;
+        };
+        let Some(path) = file.path() else {
+            eprintln!("Failed to export the scrollback: {} is not a local file", file.uri());
+            return;
+        };
+        if let Err(err) = write(&terminal, &config, &path) {
+            eprintln!("Failed to export the scrollback: {}", err);
+        }
+    });
+}
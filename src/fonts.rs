@@ -0,0 +1,125 @@
+//
+// Terminal font
+//
+// Read from the [Settings] section of termint.ini:
+//
+// font = Monospace 12          (a Pango font description)
+// font_scale = 1.0             (0.25 .. 4.0)
+// cell_width_scale = 1.0       (1.0 .. 2.0, extra letter spacing)
+// cell_height_scale = 1.0      (1.0 .. 2.0, extra line spacing)
+// bold_is_bright = false       (bold text also uses the bright colours)
+//
+
+use gtk4::pango::{
+    self,
+    prelude::*,
+    FontDescription,
+};
+
+use ini::Properties;
+
+use vte4::{
+    Terminal,
+    TerminalExt,
+};
+
+pub const DEFAULT_FONT: &str = "Monospace 12";
+const FALLBACK_FAMILY: &str = "Monospace";
+
+// Font aliases provided by fontconfig, always available
+const GENERIC_FAMILIES: &[&str] = &["monospace", "sans", "sans-serif", "serif"];
+
+pub const MIN_FONT_SCALE: f64 = 0.25;
+pub const MAX_FONT_SCALE: f64 = 4.0;
+const MIN_CELL_SCALE: f64 = 1.0;
+const MAX_CELL_SCALE: f64 = 2.0;
+
+#[derive(Debug, Clone)]
+pub struct Font {
+    desc: FontDescription,
+    scale: f64,
+    cell_width_scale: f64,
+    cell_height_scale: f64,
+    bold_is_bright: bool,
+}
+
+fn get_f64(settings: &Properties, key: &str, min: f64, max: f64) -> f64 {
+    match settings.get(key).map(str::trim).filter(|v| !v.is_empty()) {
+        Some(value) => match value.parse::<f64>() {
+            Ok(scale) if scale.is_finite() => scale.clamp(min, max),
+            _ => {
+                eprintln!("Warning: invalid value \"{}\" for \"{}\"", value, key);
+                1.0
+            }
+        },
+        None => 1.0,
+    }
+}
+
+impl Font {
+
+    pub fn default_settings(settings: &mut ini::SectionSetter) {
+        settings
+            .set("font", DEFAULT_FONT)
+            .set("font_scale", "1.0")
+            .set("cell_width_scale", "1.0")
+            .set("cell_height_scale", "1.0")
+            .set("bold_is_bright", "false");
+    }
+
+    // `context` is used to check that the font family is installed
+    pub fn from_settings(settings: &Properties, context: &pango::Context) -> Self {
+        let font = settings.get("font")
+            .map(str::trim)
+            .filter(|font| !font.is_empty())
+            .unwrap_or(DEFAULT_FONT);
+
+        let mut desc = FontDescription::from_string(font);
+
+        let family = desc.family()
+            .map(|family| family.to_string())
+            .unwrap_or_default();
+
+        // only the first family of a list like "Fira Code, monospace" is checked
+        let first = family.split(',').next().unwrap_or_default().trim();
+
+        let installed = !first.is_empty() && (
+            GENERIC_FAMILIES.iter().any(|generic| generic.eq_ignore_ascii_case(first))
+            || context.list_families()
+                .iter()
+                .any(|f| f.name().eq_ignore_ascii_case(first))
+        );
+
+        if !installed {
+            eprintln!(
+                "Warning: font \"{}\" not found, falling back to \"{}\"",
+                font, FALLBACK_FAMILY,
+            );
+            desc.set_family(FALLBACK_FAMILY);
+        }
+
+        if desc.size() <= 0 {
+            desc.set_size(FontDescription::from_string(DEFAULT_FONT).size());
+        }
+
+        Font {
+            desc,
+            scale: get_f64(settings, "font_scale", MIN_FONT_SCALE, MAX_FONT_SCALE),
+            cell_width_scale: get_f64(settings, "cell_width_scale", MIN_CELL_SCALE, MAX_CELL_SCALE),
+            cell_height_scale: get_f64(settings, "cell_height_scale", MIN_CELL_SCALE, MAX_CELL_SCALE),
+            bold_is_bright: crate::get_bool(settings, "bold_is_bright", false),
+        }
+    }
+
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    pub fn apply(&self, terminal: &Terminal) {
+        terminal.set_font_desc(Some(&self.desc));
+        terminal.set_font_scale(self.scale);
+        terminal.set_cell_width_scale(self.cell_width_scale);
+        terminal.set_cell_height_scale(self.cell_height_scale);
+        terminal.set_bold_is_bright(self.bold_is_bright);
+    }
+}
@@ -0,0 +1,115 @@
+//
+// Keyboard shortcuts
+//
+// Every shortcut is a named gio action. The [Keybindings] section of
+// termint.ini maps an action name to a space separated list of
+// accelerators, e.g. "copy = <Ctrl><Shift>c <Ctrl>Insert". An empty
+// value (or "none") unbinds the action.
+//
+
+use gtk4::{
+    prelude::*,
+    Application,
+};
+
+use ini::{Ini, Properties};
+
+// (action, default accelerators)
+pub const DEFAULT_KEYBINDINGS: &[(&str, &str)] = &[
+    ("app.new-window", "<Ctrl><Shift>n"),
+    ("app.preferences", ""),
+    ("win.close-window", "<Ctrl><Shift>q"),
+    ("win.new-tab", "<Ctrl><Shift>t"),
+    ("win.close-tab", "<Ctrl><Shift>w"),
+    ("win.next-tab", "<Ctrl>Page_Down"),
+    ("win.prev-tab", "<Ctrl>Page_Up"),
+    ("win.split-right", "<Ctrl><Shift>e"),
+    ("win.split-down", "<Ctrl><Shift>o"),
+    ("win.close-pane", "<Ctrl><Shift>x"),
+    ("win.zoom-pane", "<Ctrl><Shift>z"),
+    ("win.focus-left", ""),
+    ("win.focus-right", ""),
+    ("win.focus-up", ""),
+    ("win.focus-down", ""),
+    ("win.resize-left", ""),
+    ("win.resize-right", ""),
+    ("win.resize-up", ""),
+    ("win.resize-down", ""),
+    ("win.copy", "<Ctrl><Shift>c"),
+    ("win.paste", "<Ctrl><Shift>v"),
+    ("win.select-all", "<Ctrl><Shift>a"),
+    ("win.search", "<Ctrl><Shift>f"),
+    ("win.find-next", "<Ctrl><Shift>g"),
+    ("win.find-previous", "<Ctrl><Shift>h"),
+    ("win.zoom-in", "<Ctrl>plus <Ctrl>equal <Ctrl>KP_Add"),
+    ("win.zoom-out", "<Ctrl>minus <Ctrl>KP_Subtract"),
+    ("win.zoom-reset", "<Ctrl>0 <Ctrl>KP_0"),
+    ("win.scroll-page-up", "<Shift>Page_Up"),
+    ("win.scroll-page-down", "<Shift>Page_Down"),
+    ("win.scroll-top", "<Shift>Home"),
+    ("win.scroll-bottom", "<Shift>End"),
+    ("win.reset", ""),
+    ("win.clear-scrollback", "<Ctrl><Shift>k"),
+    ("win.toggle-opacity", ""),
+    ("win.toggle-recording", ""),
+    ("win.export-scrollback", ""),
+];
+
+// The ini key of an action is its name without the "app."/"win." prefix
+fn key_of(action: &str) -> &str {
+    action.split_once('.').map(|(_, name)| name).unwrap_or(action)
+}
+
+// The full name of an action from its name in [Keybindings]
+pub fn action_of(key: &str) -> Option<&'static str> {
+    DEFAULT_KEYBINDINGS.iter()
+        .map(|(action, _)| *action)
+        .find(|action| key_of(action) == key)
+}
+
+// Writes the [Keybindings] section with the default shortcuts
+pub fn default_section(ini: &mut Ini) {
+    let mut section = ini.with_section(Some("Keybindings"));
+    for (action, accels) in DEFAULT_KEYBINDINGS {
+        section.set(key_of(action), *accels);
+    }
+}
+
+// Parses a list of accelerators, dropping (and reporting) invalid ones
+fn parse_accels(action: &str, value: &str) -> Vec<String> {
+    if value.trim().eq_ignore_ascii_case("none") {
+        return Vec::new();
+    }
+    value
+        .split_whitespace()
+        .filter(|accel| {
+            let valid = gtk4::accelerator_parse(*accel).is_some();
+            if !valid {
+                eprintln!("Warning: invalid accelerator \"{}\" for \"{}\"", accel, key_of(action));
+            }
+            valid
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+// Binds every action to the accelerators from the [Keybindings] section,
+// falling back to the defaults for actions that are not listed
+pub fn apply(app: &Application, section: Option<&Properties>) {
+    for (action, default) in DEFAULT_KEYBINDINGS {
+        let value = section
+            .and_then(|s| s.get(key_of(action)))
+            .unwrap_or(default);
+        let accels = parse_accels(action, value);
+        let accels: Vec<&str> = accels.iter().map(String::as_str).collect();
+        app.set_accels_for_action(action, &accels);
+    }
+
+    if let Some(section) = section {
+        for (key, _) in section.iter() {
+            if !DEFAULT_KEYBINDINGS.iter().any(|(action, _)| key_of(action) == key) {
+                eprintln!("Warning: unknown action \"{}\" in [Keybindings]", key);
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//
+// Clickable links
+//
+// Plain URLs in the output are found with regexes, explicit hyperlinks
+// come from OSC 8 escape sequences. Both are opened with Ctrl+click in
+// the default application for the URI.
+//
+
+use gtk4::{
+    gdk,
+    gio,
+    prelude::*,
+    EventSequenceState,
+    GestureClick,
+    PropagationPhase,
+};
+
+use vte4::{
+    Regex,
+    Terminal,
+    TerminalExt,
+};
+
+use crate::pcre2;
+
+const URL_PATTERNS: &[&str] = &[
+    // scheme://...
+    r#"\b(?:https?|ftps?|file|sftp|ssh|git|smb|nfs)://[^\s<>"'`{}|\\^]*[^\s<>"'`{}|\\^.,;:!?)\]]"#,
+    // www.example.com/...
+    r#"\bwww\.[\w-]+(?:\.[\w-]+)+(?:/[^\s<>"'`{}|\\^]*[^\s<>"'`{}|\\^.,;:!?)\]])?"#,
+    // user@example.com
+    r#"\b(?:mailto:)?[\w.%+-]+@[\w-]+(?:\.[\w-]+)*\.[a-zA-Z]{2,}\b"#,
+];
+
+// Registers the URL regexes and enables OSC 8 hyperlinks on a terminal
+pub fn setup(terminal: &Terminal) {
+    terminal.set_allow_hyperlink(true);
+
+    for pattern in URL_PATTERNS {
+        match Regex::for_match(pattern, pcre2::DEFAULT) {
+            Ok(regex) => {
+                let tag = terminal.match_add_regex(&regex, 0);
+                terminal.match_set_cursor_name(tag, "pointer");
+            }
+            Err(err) => eprintln!("Invalid URL pattern {}: {}", pattern, err),
+        }
+    }
+
+    // Show the target of an OSC 8 hyperlink (the text may say anything)
+    terminal.connect_hyperlink_hover_uri_notify(|term| {
+        let uri = term.hyperlink_hover_uri();
+        term.set_tooltip_text(uri.as_deref());
+    });
+
+    // Ctrl+click opens the link under the pointer
+    let gesture = GestureClick::new();
+    gesture.set_button(gdk::BUTTON_PRIMARY);
+    // run before the terminal starts a selection
+    gesture.set_propagation_phase(PropagationPhase::Capture);
+    gesture.connect_pressed(|gesture, _, x, y| {
+        if !gesture.current_event_state().contains(gdk::ModifierType::CONTROL_MASK) {
+            return;
+        }
+        let Ok(terminal) = gesture.widget().downcast::<Terminal>() else {
+            return;
+        };
+        if let Some(uri) = uri_at(&terminal, x, y) {
+            gesture.set_state(EventSequenceState::Claimed);
+            open(&terminal, &uri);
+        }
+    });
+    terminal.add_controller(gesture);
+}
+
+// The hyperlink or URL at a position of the terminal, ready to be opened
+pub fn uri_at(terminal: &Terminal, x: f64, y: f64) -> Option<String> {
+    if let Some(uri) = terminal.check_hyperlink_at(x, y) {
+        return Some(uri.to_string());
+    }
+
+    let (text, _) = terminal.check_match_at(x, y);
+    let text = text?;
+
+    // add the scheme that was left out
+    Some(if text.contains("://") || text.starts_with("mailto:") {
+        text.to_string()
+    } else if text.starts_with("www.") {
+        format!("http://{}", text)
+    } else if text.contains('@') {
+        format!("mailto:{}", text)
+    } else {
+        text.to_string()
+    })
+}
+
+// Opens a URI in the default application
+pub fn open(widget: &impl IsA<gtk4::Widget>, uri: &str) {
+    let context = widget.display().app_launch_context();
+    if let Err(err) = gio::AppInfo::launch_default_for_uri(uri, Some(&context)) {
+        eprintln!("Failed to open {}: {}", uri, err);
+    }
+}
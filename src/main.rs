@@ -14,12 +14,16 @@
 // use gio::ApplicationFlags;
 
 use gtk4::{
-    gio::Cancellable,
+    gio::{ApplicationCommandLine, Cancellable, SimpleAction},
+    glib::{self, translate::ToGlibPtr, WeakRef},
     prelude::*,
     Application,
     ApplicationWindow,
+    Button,
+    Label,
+    Notebook,
+    Orientation,
     ScrolledWindow,
-    CssProvider,
 };
 
 use vte4::{
@@ -27,24 +31,66 @@ use vte4::{
     PtyFlags,
     Terminal,
     TerminalExt,
+    TerminalExtManual,
 };
 
 use std::{
+    cell::{Cell, OnceCell, RefCell},
     env,
+    ffi::CString,
+    rc::Rc,
     fs,
     fs::File,
     io::Write,
     path::{Path, PathBuf},
+    time::Instant,
 };
 
 use clap::{
     Command,
     Arg,
     ArgAction,
+    ArgMatches,
     value_parser,
 };
 use ini::Ini;
 
+mod asciicast;
+mod background;
+mod clipboard;
+mod colors;
+mod config;
+mod context_menu;
+mod cwd;
+mod dropdown;
+mod environment;
+mod export;
+mod fonts;
+mod keybindings;
+mod links;
+mod notify;
+mod on_exit;
+mod panes;
+mod pcre2;
+mod play;
+mod profiles;
+mod proxy;
+mod record;
+mod remote;
+mod scrolling;
+mod search;
+mod session;
+mod zoom;
+
+use background::Background;
+use colors::Colors;
+use config::Config;
+use dropdown::Dropdown;
+use fonts::Font;
+use on_exit::OnExit;
+use scrolling::Scrolling;
+use search::Search;
+
 // const APP_ID: &str = "org.gtk_rs.Termint";
 const APP_NAME: &str = "termint";
 const APP_TITLE: &str = "Termint";
@@ -53,32 +99,195 @@ const VERSION: &str = "0.0.1";
 const DEFAULT_WIDTH: usize = 680;
 const DEFAULT_HEIGHT: usize = 364;
 
-#[derive(Debug)]
-struct AppTerm {
-    app_id: String,
-    ini_file: PathBuf,
+// Accepts the usual ways of writing a boolean in the ini file
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+// Reads a boolean setting, warning about values that are not one
+fn get_bool(settings: &ini::Properties, key: &str, default: bool) -> bool {
+    match settings.get(key) {
+        Some(value) => parse_bool(value).unwrap_or_else(|| {
+            eprintln!("Warning: invalid value \"{}\" for \"{}\"", value, key);
+            default
+        }),
+        None => default,
+    }
+}
+
+// What every new terminal in a window is spawned with
+#[derive(Clone)]
+struct TermOptions {
+    config: Rc<Config>,
+    login_shell: String,
+    working_dir: String,
+    env: Vec<(String, String)>,
+    clean_env: bool,
+    // keep every terminal open when its child exits
+    hold: bool,
+    profile: Option<String>,
+    terminals: Rc<remote::Registry>,
+    recordings: Rc<record::Recordings>,
+    // save the buffer here when the child exits (--dump-on-exit)
+    dump_on_exit: Option<PathBuf>,
+}
+
+// What a window is opened with, from the command line of this or of a
+// later invocation
+#[derive(Clone)]
+struct Launch {
     login_shell: String,
     working_dir: String,
     window_size: (usize, usize),
     command: String,
+    env: Vec<(String, String)>,
+    clean_env: bool,
+    hold: bool,
+    // show the drop-down window instead of opening a new one
+    dropdown: bool,
+    // hide the drop-down window if it is visible
+    toggle: bool,
+    profile: Option<String>,
+    // record the first terminal of the window (--record)
+    record: Option<PathBuf>,
+    // play a recording instead of opening a terminal (--play)
+    play: Option<play::Options>,
+    // save the buffer of the first terminal when its command exits
+    dump_on_exit: Option<PathBuf>,
+}
+
+impl Launch {
+
+    // Reads the window options; relative paths are resolved against `cwd`
+    // and `shell` is the $SHELL of the invocation. The profile comes first
+    // and the other options override it.
+    fn from_matches(matches: &ArgMatches, cwd: &Path, shell: Option<String>, ini: &Ini) -> Result<Self, String> {
+        let profile_name = profiles::resolve(ini, matches.get_one::<String>("profile"))?;
+        let profile = profile_name.as_ref()
+            .and_then(|name| profiles::section(ini, name))
+            .cloned()
+            .unwrap_or_default();
+
+        let login_shell = match matches.get_one::<PathBuf>("login_shell") {
+            Some(shell) => {
+                let shell = cwd.join(shell);
+                if !shell.is_file() {
+                    return Err(format!("The specified login shell does not exist: {}", shell.display()));
+                }
+                shell.to_string_lossy().to_string()
+            }
+            None => match profile.get("shell").map(str::trim).filter(|shell| !shell.is_empty()) {
+                Some(shell) => shell.to_string(),
+                None => shell.ok_or_else(|| "Unable to get the user's shell".to_string())?,
+            },
+        };
+
+        let working_dir = match matches.get_one::<PathBuf>("working_directory") {
+            Some(dir) => {
+                let dir = cwd.join(dir);
+                if !dir.is_dir() {
+                    return Err(format!("The specified working directory does not exist: {}", dir.display()));
+                }
+                dir.to_string_lossy().to_string()
+            }
+            None => profiles::working_directory(&profile).unwrap_or_default(),
+        };
+
+        let window_size = match matches.get_one::<String>("window_size_pixels") {
+            Some(size) => {
+                let parts: Vec<&str> = size.split('x').collect();
+                if parts.len() != 2 {
+                    return Err(format!("Invalid window size: {}", size));
+                }
+                (
+                    parts[0].parse::<usize>().unwrap_or(DEFAULT_WIDTH),
+                    parts[1].parse::<usize>().unwrap_or(DEFAULT_HEIGHT),
+                )
+            }
+            // 0 leaves the size of [Settings]
+            None => (
+                profile.get("default_width").and_then(|w| w.trim().parse::<usize>().ok()).unwrap_or(0),
+                profile.get("default_height").and_then(|h| h.trim().parse::<usize>().ok()).unwrap_or(0),
+            ),
+        };
+
+        let play = match matches.get_one::<PathBuf>("play") {
+            Some(path) => {
+                let speed = matches.get_one::<f64>("speed").copied().unwrap_or(1.0);
+                if !(speed > 0.0 && speed.is_finite()) {
+                    return Err(format!("Invalid speed: {}", speed));
+                }
+                let idle_limit = matches.get_one::<f64>("idle_limit").copied();
+                if let Some(limit) = idle_limit.filter(|limit| limit.is_nan() || *limit <= 0.0) {
+                    return Err(format!("Invalid idle limit: {}", limit));
+                }
+                Some(play::Options {
+                    path: cwd.join(path),
+                    speed,
+                    idle_limit,
+                })
+            }
+            None => None,
+        };
+
+        let mut env = profiles::env(&profile);
+        for var in matches.get_many::<String>("env").into_iter().flatten() {
+            env.push(environment::parse_var(var)
+                .ok_or_else(|| format!("Invalid environment variable: {}", var))?);
+        }
+
+        Ok(Launch {
+            login_shell,
+            working_dir,
+            window_size,
+            command: matches.get_one::<String>("execute")
+                .cloned()
+                .or_else(|| profile.get("command").map(str::to_string))
+                .unwrap_or_default(),
+            env,
+            clean_env: matches.get_flag("clean_env"),
+            hold: matches.get_flag("hold"),
+            dropdown: matches.get_flag("dropdown") || matches.get_flag("toggle"),
+            toggle: matches.get_flag("toggle"),
+            profile: profile_name,
+            record: matches.get_one::<PathBuf>("record").map(|path| cwd.join(path)),
+            play,
+            dump_on_exit: matches.get_one::<PathBuf>("dump_on_exit").map(|path| cwd.join(path)),
+        })
+    }
+}
+
+struct AppTerm {
+    app_id: String,
+    ini_file: PathBuf,
+    // the options of the command line that started this instance
+    launch: Launch,
+    terminals: Rc<remote::Registry>,
+    recordings: Rc<record::Recordings>,
+    // the window of --dropdown, kept while it is hidden
+    dropdown: RefCell<WeakRef<ApplicationWindow>>,
+    // its slide in or out
+    dropdown_slide: Rc<dropdown::Slide>,
+    // read by new to resolve the profile, handed over to the config
+    ini: RefCell<Option<Ini>>,
+    // reopen the windows of the last session (--restore)
+    restore: bool,
+    // loaded when the application starts up
+    config: OnceCell<Rc<Config>>,
 }
 
 impl AppTerm {
 
     fn default_style() -> String {
-        format!(r#"
-.scrolled-window {{
-    /* background-image: url(\".config/{}/background.jpg\"); */
-    background-size: cover;
-    background-repeat: no-repeat;
-    background-position: center;
+        // the background image and opacity are settings of termint.ini
+        r#"
+.scrolled-window {
     background-color: rgba(255, 255, 255, 0);
-}}
-.terminal {{
-    opacity: 0.92;
-    font-size: 12px;
-    font-family: monospace;
-}}"#, APP_NAME)
+}"#.to_string()
     }
 
     fn default_styles_file(file_path: &PathBuf) {
@@ -97,7 +306,24 @@ impl AppTerm {
         ini.with_section(Some("Settings"))
             .set("default_width", DEFAULT_WIDTH.to_string())
             .set("default_height", DEFAULT_HEIGHT.to_string())
-            .set("icon_name", "computer");
+            .set("icon_name", "computer")
+            .set("clean_environment", "false")
+            .set("on_exit", "close")
+            .set("default_profile", "")
+            .set("restore_session", "false")
+            .set("title_template", cwd::DEFAULT_TITLE_TEMPLATE);
+
+        Font::default_settings(&mut ini.with_section(Some("Settings")));
+        zoom::default_settings(&mut ini.with_section(Some("Settings")));
+        Scrolling::default_settings(&mut ini.with_section(Some("Settings")));
+        clipboard::default_settings(&mut ini.with_section(Some("Settings")));
+        Dropdown::default_settings(&mut ini.with_section(Some("Settings")));
+        Background::default_settings(&mut ini.with_section(Some("Settings")));
+        record::default_settings(&mut ini.with_section(Some("Settings")));
+        notify::default_settings(&mut ini.with_section(Some("Settings")));
+
+        Colors::default_section(&mut ini);
+        keybindings::default_section(&mut ini);
 
         if let Some(dir) = config_dir {
             let styles_file = dir.join("styles.css");
@@ -114,11 +340,7 @@ impl AppTerm {
     }
 
 
-    fn make_terminal(
-        login_shell: &str,
-        working_directory: &str,
-        command: &str,
-    ) -> Terminal {
+    fn make_terminal() -> Terminal {
 
         // https://python-forum.io/thread-16720.html
         let terminal = Terminal::new();
@@ -126,9 +348,21 @@ impl AppTerm {
         terminal.set_focus_on_click(true);
         terminal.set_can_focus(true);
 
-        // set terminal font from a string
-        // let font_description = pango::FontDescription::from_string("monospace 10");
-        // terminal.set_font_desc(Some(&font_description));
+        terminal
+    }
+
+    // Starts the shell (or the command) in a terminal, on a new PTY so
+    // it can also be used to restart it. With a stream, the output goes
+    // through a proxy PTY while the stream has listeners.
+    fn spawn_child(
+        terminal: &Terminal,
+        stream: Option<&Rc<proxy::Stream>>,
+        login_shell: &str,
+        working_directory: &str,
+        command: &str,
+        envv: &[String],
+        clean_env: bool,
+    ) {
         let flags = PtyFlags::DEFAULT;
 
         let has_cmd = !command.is_empty();
@@ -140,22 +374,13 @@ impl AppTerm {
             argv.push(command);
         }
 
-        let envv = vec![]; // Environment variables can be added here if needed
-        // Fix the issue with accented characters in some locales
-        // Ensure UTF-8 environment
-        // let lang = env::var("LANG").unwrap_or_else(|_| "C.UTF-8".to_string());
-        // let envv = vec![
-        //     format!("LANG={}", lang),
-        //     format!("LC_ALL={}", lang),
-        // ];
-        // let envv = vec![
-        //     "LANG=pt_PT.UTF-8",
-        //     "LC_ALL=pt_PT.UTF-8",
-        // ];
+        // Variables added to (or, for a clean environment, replacing)
+        // the environment inherited from termint
+        let envv: Vec<&str> = envv.iter().map(String::as_str).collect();
 
         // Spawn flags and optional child setup
         // let spawn_flags = gtk4::glib::SpawnFlags::SEARCH_PATH | gtk4::glib::SpawnFlags::DO_NOT_REAP_CHILD;
-        let spawn_flags = gtk4::glib::SpawnFlags::SEARCH_PATH;
+        let spawn_flags = environment::spawn_flags(clean_env);
 
         // println!("has_cmd: {} command: {}", has_cmd, command);
         let child_setup = move || {
@@ -175,9 +400,17 @@ impl AppTerm {
         let pty = Pty::new_sync(flags, cancellable_ref)
             .expect("Failed to create PTY");
 
+        // Link the PTY to the terminal widget, before the child starts so
+        // that it gets the size of the terminal
+        match stream {
+            Some(stream) => stream.set_child(&pty),
+            None => terminal.set_pty(Some(&pty)),
+        }
+
         // Spawn the command asynchronously within the PTY
         // https://gnome.pages.gitlab.gnome.org/vte/gtk4/method.Pty.spawn_with_fds_async.html
 
+        let terminal_weak = terminal.downgrade();
         pty.spawn_async(
             if working_directory.is_empty() {
                 None
@@ -190,26 +423,898 @@ impl AppTerm {
             child_setup,
             -1, // Set to -1 for no timeout
             cancellable_ref,
-            |pid| {
-                if let Err(err) = pid {
-                    eprintln!("Failed to spawn: {:?}", err);
+            move |pid| {
+                let Some(terminal) = terminal_weak.upgrade() else {
+                    return;
+                };
+                match pid {
+                    // child-exited is emitted when it ends
+                    Ok(pid) => terminal.watch_child(pid),
+                    Err(err) => {
+                        eprintln!("Failed to spawn: {:?}", err);
+                        terminal.feed(format!("Failed to spawn: {}\r\n", err).as_bytes());
+                    }
                 }
             },
         );
+    }
 
-        // Link the PTY to the terminal widget
-        terminal.set_pty(Some(&pty));
+    // Closes the pane of a terminal; the tab closes with its last pane
+    // and the window with its last tab
+    fn close_terminal(terminal: &Terminal) {
+        let Some(pane) = panes::pane_of(terminal) else {
+            return;
+        };
+        if !panes::close(&pane) {
+            if let Some(notebook) = terminal.ancestor(Notebook::static_type()).and_downcast::<Notebook>() {
+                if let Some(page) = Self::page_of(&notebook, &pane) {
+                    Self::close_page(&notebook, &page);
+                }
+            }
+        }
+    }
+
+    // Saves the session when `window` is the last one shown (the
+    // hidden drop-down window does not count)
+    fn save_session_if_last(window: &gtk4::Window) {
+        let Some(app) = window.application() else {
+            return;
+        };
+        let last = app.windows()
+            .iter()
+            .all(|other| other == window || !other.is_visible());
+        if last {
+            app.activate_action("save-session", None);
+        }
+    }
+
+    // The notebook page (the root of the pane tree) holding a widget
+    fn page_of(notebook: &Notebook, widget: &impl IsA<gtk4::Widget>) -> Option<gtk4::Widget> {
+        let mut current = Some(widget.as_ref().clone());
+        while let Some(widget) = current {
+            if notebook.page_num(&widget).is_some() {
+                return Some(widget);
+            }
+            current = widget.parent();
+        }
+        None
+    }
+
+    // Returns the title of a notebook page from its tab label
+    fn page_title(notebook: &Notebook, page: &gtk4::Widget) -> String {
+        notebook.tab_label(page)
+            .and_then(|tab| tab.first_child())
+            .and_downcast::<Label>()
+            .map(|label| label.text().to_string())
+            .unwrap_or_else(|| APP_TITLE.to_string())
+    }
+
+    // Removes a page (remove_page(None) would remove the last one)
+    fn close_page(notebook: &Notebook, page: &impl IsA<gtk4::Widget>) {
+        // the window closes with its last tab, save the session while
+        // the tab is still there
+        if notebook.n_pages() == 1 {
+            if let Some(window) = notebook.root().and_downcast::<gtk4::Window>() {
+                Self::save_session_if_last(&window);
+            }
+        }
+        if let Some(page_num) = notebook.page_num(page) {
+            notebook.remove_page(Some(page_num));
+        }
+    }
+
+    // The focused terminal of the window, or the first one of the
+    // current tab if the focus is somewhere else
+    fn active_terminal(window: &ApplicationWindow, notebook: &Notebook) -> Option<Terminal> {
+        if let Some(terminal) = GtkWindowExt::focus(window).and_downcast::<Terminal>() {
+            return Some(terminal);
+        }
+        notebook.current_page()
+            .and_then(|n| notebook.nth_page(Some(n)))
+            .and_then(|page| panes::terminals(&page).into_iter().next())
+    }
+
+    // Shows the title of a terminal in its tab label, and in the window
+    // title if it is in the active tab. With split panes only the
+    // focused terminal sets the title. A title set over D-Bus wins over
+    // the template.
+    fn update_titles(terminal: &Terminal, config: &Config, terminals: &remote::Registry, recordings: &record::Recordings) {
+        let Some(notebook) = terminal.ancestor(Notebook::static_type()).and_downcast::<Notebook>() else {
+            return;
+        };
+        let Some(page) = Self::page_of(&notebook, terminal) else {
+            return;
+        };
+        if !terminal.is_focus() && panes::terminals(&page).len() > 1 {
+            return;
+        }
+
+        let mut title = terminals.title_of(terminal).unwrap_or_else(|| {
+            let template = config.ini()
+                .section(Some("Settings"))
+                .and_then(|settings| settings.get("title_template"))
+                .unwrap_or(cwd::DEFAULT_TITLE_TEMPLATE)
+                .to_string();
+            cwd::title(&template, terminal)
+        });
+        if recordings.is_recording(terminal) {
+            title.insert_str(0, "● ");
+        }
+
+        if let Some(label) = notebook.tab_label(&page)
+            .and_then(|tab| tab.first_child())
+            .and_downcast::<Label>() {
+            label.set_text(&title);
+        }
+
+        if notebook.current_page() == notebook.page_num(&page) {
+            if let Some(window) = terminal.root().and_downcast::<gtk4::Window>() {
+                window.set_title(Some(&title));
+            }
+        }
+    }
+
+    // Builds a pane: a scrolled window wrapping a new terminal
+    fn make_pane(options: &TermOptions, command: &str) -> (ScrolledWindow, Terminal) {
+
+        let scrolled_window = ScrolledWindow::builder()
+            .hexpand(true)
+            .vexpand(true)
+            .build();
+        options.config.apply_to_pane(&scrolled_window);
+
+        let sw_style_context = scrolled_window.style_context();
+        sw_style_context.add_class("scrolled-window");
+        sw_style_context.add_provider(&options.config.css_provider(), gtk4::STYLE_PROVIDER_PRIORITY_APPLICATION);
+
+        let terminal = Self::make_terminal();
+        let terminal_id = options.terminals.register(
+            &terminal,
+            command,
+            &options.working_dir,
+            options.profile.as_deref(),
+        );
+
+        let (envv, clean_env) = {
+            let ini = options.config.ini();
+            let clean_env = options.clean_env || ini.section(Some("Settings"))
+                .map(|settings| get_bool(settings, "clean_environment", false))
+                .unwrap_or(false);
+            let mut extra = options.env.clone();
+            extra.push((remote::TERMINAL_ID_VAR.to_string(), terminal_id.to_string()));
+            (
+                environment::child_env(ini.section(Some("Environment")), &extra, clean_env),
+                clean_env,
+            )
+        };
+
+        let stream = options.terminals.stream_of(&terminal);
+        Self::spawn_child(
+            &terminal,
+            stream.as_ref(),
+            &options.login_shell,
+            &options.working_dir,
+            command,
+            &envv,
+            clean_env,
+        );
+
+        let term_style_context = terminal.style_context();
+        term_style_context.add_class("terminal");
+        term_style_context.add_provider(&options.config.css_provider(), gtk4::STYLE_PROVIDER_PRIORITY_APPLICATION);
+
+        options.config.apply(&terminal);
+
+        links::setup(&terminal);
+        context_menu::setup(&terminal, &options.config);
+        zoom::setup(&terminal, &options.config);
+        notify::setup(&terminal, terminal_id, &options.config);
+
+        // the opacity follows the window the terminal is shown in
+        let config = options.config.clone();
+        terminal.connect_map(move |term| {
+            config.apply_background(term);
+        });
+
+        /*
+        let win = window.clone();
+        terminal.connect_window_title_changed(move |terminal| {
+            if let Some(title) = terminal.window_title() {
+                // println!("Window title changed: {:?}", title);
+                win.set_title(Some(&title));
+            }
+        });
+        */
+
+        // Update the tab label and the window title when the terminal
+        // title or directory changes or the terminal gets the focus
+        let config = options.config.clone();
+        let terminals = options.terminals.clone();
+        let recordings = options.recordings.clone();
+        terminal.connect_window_title_changed(move |term| {
+            Self::update_titles(term, &config, &terminals, &recordings);
+        });
+        let config = options.config.clone();
+        let terminals = options.terminals.clone();
+        let recordings = options.recordings.clone();
+        terminal.connect_current_directory_uri_notify(move |term| {
+            Self::update_titles(term, &config, &terminals, &recordings);
+        });
+        let config = options.config.clone();
+        let terminals = options.terminals.clone();
+        let recordings = options.recordings.clone();
+        terminal.connect_has_focus_notify(move |term| {
+            if term.has_focus() {
+                Self::update_titles(term, &config, &terminals, &recordings);
+            }
+        });
+
+        // quit the application
+        // let app_clone = app.clone();
+        // terminal.connect_eof(move |_terminal|{
+        //     app_clone.quit();
+        // });
+
+        // Close, hold or restart when the child exits. The policy is read
+        // when it happens, so a reload of the settings applies to running
+        // terminals too.
+        let config = options.config.clone();
+        let hold = options.hold;
+        let login_shell = options.login_shell.clone();
+        let working_dir = options.working_dir.clone();
+        let command = command.to_string();
+        let started = Cell::new(Instant::now());
+        // only the first exit is saved, a restart does not overwrite it
+        let dump_on_exit = RefCell::new(options.dump_on_exit.clone());
+        terminal.connect_child_exited(move |term, status| {
+            if let Some(path) = dump_on_exit.take() {
+                if let Err(err) = export::write(term, &config, &path) {
+                    eprintln!("Failed to save the scrollback: {}", err);
+                }
+            }
+
+            let on_exit = if hold {
+                OnExit::Hold
+            } else {
+                OnExit::from_settings(config.ini().section(Some("Settings")))
+            };
+
+            match on_exit {
+                OnExit::Close => Self::close_terminal(term),
+                OnExit::Hold => {
+                    term.feed(format!("\r\n[Process {}]\r\n", on_exit::describe_status(status)).as_bytes());
+                    term.set_input_enabled(false);
+                }
+                OnExit::Restart if started.get().elapsed() < on_exit::MIN_RUNTIME => {
+                    term.feed(format!(
+                        "\r\n[Process {}, exited too quickly to restart]\r\n",
+                        on_exit::describe_status(status),
+                    ).as_bytes());
+                    term.set_input_enabled(false);
+                }
+                OnExit::Restart => {
+                    term.feed(format!("\r\n[Process {}, restarting]\r\n", on_exit::describe_status(status)).as_bytes());
+                    started.set(Instant::now());
+                    Self::spawn_child(term, stream.as_ref(), &login_shell, &working_dir, &command, &envv, clean_env);
+                }
+            }
+        });
+
+        // this code is for testing purposes only
+        /*
+        let app_clone = app.clone();
+        // quit the application
+        terminal.connect_commit(move |_terminal, input, _s| {
+            unsafe {
+                println!("Debug Input: {} Buffer: {:?}", input, BUFFER);
+                if input == "\u{7f}" { // backspace
+                    if !BUFFER.is_empty() {
+                        BUFFER.pop();
+                    }
+                    return;
+                }
+
+                if BUFFER.len() < "exit\r".len() {
+                    BUFFER.push_str(input);
+                    if input == "\r" {
+                        if BUFFER.eq_ignore_ascii_case("exit\r") {
+                            println!("Debug Exit Input: {} Buffer: {:?}", input, BUFFER);
+                            app_clone.quit();
+                        }
+                    }
+                }
+                if input == "\r" {
+                    BUFFER.clear();
+                }
+            }
+        });
+        */
+
+        /*
+        // alternative approach
+        let buffer = Arc::new(Mutex::new(String::new()));
+        terminal.connect_commit(move |_terminal, input, _s| {
+            // println!("Debug Input: {} Buffer: {:?}", input, buffer);
+            if buffer.lock().unwrap().len() < "exit\r".len() {
+                buffer.lock().unwrap().push_str(input);
+                if input == "\r" {
+                    if buffer.lock().unwrap().eq_ignore_ascii_case("exit\r") {
+                        app_clone.quit();
+                    }
+                }
+            }
+            if input == "\r" {
+                buffer.lock().unwrap().clear();
+            }
+        });
+        */
+
+        // 1. Middle-Click to Paste (Linux Primary Selection)
+        let terminal_middle_click = terminal.downgrade();
+        let middle_gesture = gtk4::GestureClick::new();
+        middle_gesture.set_button(2); // Button 2 is the Middle Mouse Button
+        // run before VTE pastes on its own, skipping the paste checks
+        middle_gesture.set_propagation_phase(gtk4::PropagationPhase::Capture);
+
+        let config = options.config.clone();
+        let terminals = options.terminals.clone();
+        middle_gesture.connect_pressed(move |gesture, _, _, _| {
+            // This pastes from the PRIMARY selection (what you just highlighted)
+            if let Some(terminal) = terminal_middle_click.upgrade() {
+                gesture.set_state(gtk4::EventSequenceState::Claimed);
+                clipboard::paste(&terminal, terminals.stream_of(&terminal), &config, true);
+            }
+        });
+        terminal.add_controller(middle_gesture);
+
+        // 2. Auto-copy on Highlight
+        // This ensures that as soon as you select text, it's ready for middle-click
+        let config = options.config.clone();
+        terminal.connect_selection_changed(move |term| {
+            clipboard::copy_on_select(term, &config);
+        });
+
+        scrolled_window.set_child(Some(&terminal));
+
+        (scrolled_window, terminal)
+    }
+
+    fn add_tab(notebook: &Notebook, options: &TermOptions, command: &str) -> Terminal {
+
+        let (pane, terminal) = Self::make_pane(options, command);
+
+        // The page is the root of the pane tree
+        let page = gtk4::Box::new(Orientation::Horizontal, 0);
+        page.append(&pane);
+
+        // Tab label with a close button
+        let label = Label::new(Some(APP_TITLE));
+        let close_button = Button::from_icon_name("window-close-symbolic");
+        close_button.set_has_frame(false);
+        close_button.set_focus_on_click(false);
+        let tab_label = gtk4::Box::new(Orientation::Horizontal, 4);
+        tab_label.append(&label);
+        tab_label.append(&close_button);
+
+        let notebook_weak = notebook.downgrade();
+        let page_weak = page.downgrade();
+        close_button.connect_clicked(move |_| {
+            if let (Some(notebook), Some(page)) = (notebook_weak.upgrade(), page_weak.upgrade()) {
+                Self::close_page(&notebook, &page);
+            }
+        });
+
+        let page_num = notebook.append_page(&page, Some(&tab_label));
+        notebook.set_tab_reorderable(&page, true);
+        notebook.set_current_page(Some(page_num));
+        terminal.grab_focus();
 
         terminal
     }
 
-    fn create(&self) {
+    // The notebook holding the tabs of a window
+    fn notebook_of(window: &ApplicationWindow) -> Option<Notebook> {
+        window.child()
+            .and_then(|content| content.last_child())
+            .and_downcast::<Notebook>()
+    }
+
+    // Adds a window action that runs on the active terminal
+    fn add_terminal_action(
+        window: &ApplicationWindow,
+        notebook: &Notebook,
+        name: &str,
+        callback: impl Fn(&Terminal) + 'static,
+    ) {
+        let action = SimpleAction::new(name, None);
+        let window_weak = window.downgrade();
+        let notebook_weak = notebook.downgrade();
+        action.connect_activate(move |_, _| {
+            if let (Some(window), Some(notebook)) = (window_weak.upgrade(), notebook_weak.upgrade()) {
+                if let Some(terminal) = Self::active_terminal(&window, &notebook) {
+                    callback(&terminal);
+                }
+            }
+        });
+        window.add_action(&action);
+    }
+
+    // The options with the directory the shell of `terminal` is in, if
+    // it reports one
+    fn in_cwd_of(options: &Rc<TermOptions>, terminal: Option<&Terminal>) -> Rc<TermOptions> {
+        match terminal.and_then(cwd::of) {
+            Some(dir) => Rc::new(TermOptions {
+                working_dir: dir,
+                ..(**options).clone()
+            }),
+            None => options.clone(),
+        }
+    }
+
+    // Splits the active pane, running a new shell in the new pane
+    fn split_pane(
+        window: &ApplicationWindow,
+        notebook: &Notebook,
+        options: &Rc<TermOptions>,
+        orientation: Orientation,
+    ) {
+        let Some(active) = Self::active_terminal(window, notebook) else {
+            return;
+        };
+        let Some(pane) = panes::pane_of(&active) else {
+            return;
+        };
+        let options = Self::in_cwd_of(options, Some(&active));
+        let (new_pane, terminal) = Self::make_pane(&options, "");
+        panes::split(&pane, new_pane.upcast_ref(), orientation);
+        terminal.grab_focus();
+    }
+
+    // Writes the default termint.ini (and styles.css) if there is none
+    fn init_settings(config_dir: &PathBuf, ini_file: &Path) -> std::io::Result<()> {
+        fs::create_dir_all(config_dir)?;
+        if !ini_file.exists() {
+            Self::default_ini(Some(config_dir)).write_to_file(ini_file)?;
+        }
+        Ok(())
+    }
+
+    fn load_config(ini_file: &Path) -> Ini {
+        if ini_file.exists() {
+            Ini::load_from_file(ini_file).unwrap_or_else(|err| {
+                eprintln!("failed to parse config file, using the defaults: {}", err);
+                Self::default_ini(None)
+            })
+        } else {
+            Self::default_ini(None)
+        }
+    }
+
+    fn config(&self) -> &Rc<Config> {
+        self.config.get()
+            .expect("the configuration is loaded on startup")
+    }
+
+    fn session_file(&self) -> PathBuf {
+        self.ini_file.with_file_name("session.ini")
+    }
+
+    fn term_options(&self, launch: &Launch) -> Rc<TermOptions> {
+        Rc::new(TermOptions {
+            config: self.config().clone(),
+            login_shell: launch.login_shell.clone(),
+            working_dir: launch.working_dir.clone(),
+            env: launch.env.clone(),
+            clean_env: launch.clean_env,
+            hold: launch.hold,
+            profile: launch.profile.clone(),
+            terminals: self.terminals.clone(),
+            recordings: self.recordings.clone(),
+            dump_on_exit: None,
+        })
+    }
+
+    // Opens a new window whose first tab runs the command of `launch`
+    // (or the shell)
+    fn open_window(&self, app: &Application, launch: &Launch) -> Option<ApplicationWindow> {
+
+        let config = self.config().clone();
+        let ini = config.ini();
+
+        let settings = match ini.section(Some("Settings")) {
+            Some(section) => section,
+            None => {
+                eprintln!("Error: No settings section found in config.");
+                app.quit();
+                return None;
+            }
+        };
+
+        let (default_width, default_height) = launch.window_size;
+
+        let win_width = if default_width == 0 {
+            settings
+                .get("default_width")
+                .and_then(|w| w.parse::<usize>().ok())
+                .unwrap_or(DEFAULT_WIDTH)
+        } else {
+            default_width
+        }
+        .max(100); // Ensure the minimum width is 100
+
+        let win_height = if default_height == 0 {
+            settings
+                .get("default_height")
+                .and_then(|h| h.parse::<usize>().ok())
+                .unwrap_or(DEFAULT_HEIGHT)
+        } else {
+            default_height
+        }
+        .max(100); // Ensure the minimum height is 100
+
+        let window = ApplicationWindow::builder()
+            .application(app)
+            .title(APP_TITLE)
+            .default_width(win_width as i32)
+            .default_height(win_height as i32)
+            .width_request(win_width as i32)
+            .height_request(win_height as i32)
+            .build();
+
+        window.connect_destroy(|_| {
+            println!("Window destroyed.");
+        });
+
+        // Closing the last window ends the session, save it
+        window.connect_close_request(|window| {
+            Self::save_session_if_last(window.upcast_ref());
+            glib::Propagation::Proceed
+        });
+
+        window.set_icon_name(settings.get("icon_name"));
+        window.add_css_class(background::WINDOW_CLASS);
+
+        drop(ini);
+
+        if launch.dropdown {
+            dropdown::setup(&window, &config, &self.dropdown_slide);
+        }
+
+        let notebook = Notebook::builder()
+            .scrollable(true)
+            .show_border(false)
+            .show_tabs(false)
+            .build();
+
+        // Only show the tab strip when there is more than one tab
+        notebook.connect_page_added(|nb, _, _| {
+            nb.set_show_tabs(nb.n_pages() > 1);
+        });
+
+        // Close the window when the last tab is gone
+        let window_weak = window.downgrade();
+        notebook.connect_page_removed(move |nb, _, _| {
+            nb.set_show_tabs(nb.n_pages() > 1);
+            if nb.n_pages() == 0 {
+                if let Some(window) = window_weak.upgrade() {
+                    window.close();
+                }
+            }
+        });
+
+        // The window title follows the active tab
+        let window_weak = window.downgrade();
+        notebook.connect_switch_page(move |nb, page, _| {
+            if let Some(window) = window_weak.upgrade() {
+                window.set_title(Some(&Self::page_title(nb, page)));
+            }
+        });
+
+        let search = Search::new();
+
+        let content = gtk4::Box::new(Orientation::Vertical, 0);
+        content.append(search.widget());
+        content.append(&notebook);
+        window.set_child(Some(&content));
+
+        let options = self.term_options(launch);
+
+        // Tab actions
+        let new_tab = SimpleAction::new("new-tab", None);
+        let window_weak = window.downgrade();
+        let notebook_weak = notebook.downgrade();
+        let opts = options.clone();
+        new_tab.connect_activate(move |_, _| {
+            if let (Some(window), Some(notebook)) = (window_weak.upgrade(), notebook_weak.upgrade()) {
+                let active = Self::active_terminal(&window, &notebook);
+                let options = Self::in_cwd_of(&opts, active.as_ref());
+                // new tabs always start the shell, not the -e command
+                Self::add_tab(&notebook, &options, "");
+            }
+        });
+        window.add_action(&new_tab);
+
+        // Runs a command in a new tab (the "run:" items of [ContextMenu])
+        let run = SimpleAction::new("run", Some(glib::VariantTy::STRING));
+        let window_weak = window.downgrade();
+        let notebook_weak = notebook.downgrade();
+        let opts = options.clone();
+        run.connect_activate(move |_, command| {
+            let Some(command) = command.and_then(|command| command.str()) else {
+                return;
+            };
+            if let (Some(window), Some(notebook)) = (window_weak.upgrade(), notebook_weak.upgrade()) {
+                let active = Self::active_terminal(&window, &notebook);
+                let options = Self::in_cwd_of(&opts, active.as_ref());
+                Self::add_tab(&notebook, &options, command);
+            }
+        });
+        window.add_action(&run);
+
+        let close_tab = SimpleAction::new("close-tab", None);
+        let notebook_weak = notebook.downgrade();
+        close_tab.connect_activate(move |_, _| {
+            if let Some(notebook) = notebook_weak.upgrade() {
+                if let Some(page) = notebook.current_page().and_then(|n| notebook.nth_page(Some(n))) {
+                    Self::close_page(&notebook, &page);
+                }
+            }
+        });
+        window.add_action(&close_tab);
+
+        let next_tab = SimpleAction::new("next-tab", None);
+        let notebook_weak = notebook.downgrade();
+        next_tab.connect_activate(move |_, _| {
+            if let Some(notebook) = notebook_weak.upgrade() {
+                // wrap around to the first tab
+                match notebook.current_page() {
+                    Some(n) if n + 1 >= notebook.n_pages() => notebook.set_current_page(Some(0)),
+                    _ => notebook.next_page(),
+                }
+            }
+        });
+        window.add_action(&next_tab);
+
+        let prev_tab = SimpleAction::new("prev-tab", None);
+        let notebook_weak = notebook.downgrade();
+        prev_tab.connect_activate(move |_, _| {
+            if let Some(notebook) = notebook_weak.upgrade() {
+                // wrap around to the last tab
+                match notebook.current_page() {
+                    Some(0) => notebook.set_current_page(None),
+                    _ => notebook.prev_page(),
+                }
+            }
+        });
+        window.add_action(&prev_tab);
+
+        // Pane actions
+        for (name, orientation) in [
+            ("split-right", Orientation::Horizontal),
+            ("split-down", Orientation::Vertical),
+        ] {
+            let split = SimpleAction::new(name, None);
+            let window_weak = window.downgrade();
+            let notebook_weak = notebook.downgrade();
+            let opts = options.clone();
+            split.connect_activate(move |_, _| {
+                if let (Some(window), Some(notebook)) = (window_weak.upgrade(), notebook_weak.upgrade()) {
+                    Self::split_pane(&window, &notebook, &opts, orientation);
+                }
+            });
+            window.add_action(&split);
+        }
+
+        Self::add_terminal_action(&window, &notebook, "close-pane", Self::close_terminal);
+
+        Self::add_terminal_action(&window, &notebook, "zoom-pane", panes::toggle_zoom);
+
+        for (name, direction) in panes::Direction::ALL {
+            Self::add_terminal_action(&window, &notebook, &format!("focus-{}", name), move |term| {
+                panes::focus_neighbour(term, direction);
+            });
+            Self::add_terminal_action(&window, &notebook, &format!("resize-{}", name), move |term| {
+                panes::resize(term, direction);
+            });
+        }
+
+        // Terminal actions
+        Self::add_terminal_action(&window, &notebook, "copy", |term| {
+            if term.has_selection() {
+                term.copy_clipboard_format(vte4::Format::Text);
+            }
+        });
+        let opts = options.clone();
+        Self::add_terminal_action(&window, &notebook, "paste", move |term| {
+            clipboard::paste(term, opts.terminals.stream_of(term), &opts.config, false);
+        });
+        Self::add_terminal_action(&window, &notebook, "select-all", |term| {
+            term.select_all();
+        });
+
+        let opts = options.clone();
+        Self::add_terminal_action(&window, &notebook, "zoom-in", move |term| {
+            zoom::zoom_in(term, &opts.config);
+        });
+        let opts = options.clone();
+        Self::add_terminal_action(&window, &notebook, "zoom-out", move |term| {
+            zoom::zoom_out(term, &opts.config);
+        });
+        let opts = options.clone();
+        Self::add_terminal_action(&window, &notebook, "zoom-reset", move |term| {
+            zoom::reset(term, &opts.config);
+        });
+
+        Self::add_terminal_action(&window, &notebook, "scroll-page-up", |term| {
+            if let Some(adj) = term.vadjustment() {
+                adj.set_value(adj.value() - adj.page_size());
+            }
+        });
+        Self::add_terminal_action(&window, &notebook, "scroll-page-down", |term| {
+            if let Some(adj) = term.vadjustment() {
+                adj.set_value(adj.value() + adj.page_size());
+            }
+        });
+        Self::add_terminal_action(&window, &notebook, "scroll-top", |term| {
+            if let Some(adj) = term.vadjustment() {
+                adj.set_value(adj.lower());
+            }
+        });
+        Self::add_terminal_action(&window, &notebook, "scroll-bottom", |term| {
+            if let Some(adj) = term.vadjustment() {
+                adj.set_value(adj.upper());
+            }
+        });
+
+        let opts = options.clone();
+        Self::add_terminal_action(&window, &notebook, "reset", move |term| {
+            term.reset(true, false);
+            if let Some(stream) = opts.terminals.stream_of(term) {
+                stream.reset();
+            }
+        });
+        Self::add_terminal_action(&window, &notebook, "clear-scrollback", |term| {
+            // dropping the scrollback to zero lines discards the history
+            let lines = term.scrollback_lines();
+            term.set_scrollback_lines(0);
+            term.set_scrollback_lines(lines);
+        });
+
+        let opts = options.clone();
+        Self::add_terminal_action(&window, &notebook, "toggle-recording", move |term| {
+            if let Some(stream) = opts.terminals.stream_of(term) {
+                opts.recordings.toggle(term, &stream, &opts.config.ini());
+            }
+            Self::update_titles(term, &opts.config, &opts.terminals, &opts.recordings);
+        });
+
+        let opts = options.clone();
+        Self::add_terminal_action(&window, &notebook, "export-scrollback", move |term| {
+            export::ask(term, &opts.config);
+        });
+
+        // Search actions
+        let find = search.clone();
+        Self::add_terminal_action(&window, &notebook, "search", move |term| {
+            find.open(term);
+        });
+
+        for (name, backwards) in [("find-next", false), ("find-previous", true)] {
+            let action = SimpleAction::new(name, None);
+            let find = search.clone();
+            action.connect_activate(move |_, _| {
+                find.find(backwards);
+            });
+            window.add_action(&action);
+        }
+
+        context_menu::add_actions(&window);
+
+        let toggle_opacity = SimpleAction::new("toggle-opacity", None);
+        let window_weak = window.downgrade();
+        let opts = options.clone();
+        toggle_opacity.connect_activate(move |_, _| {
+            if let Some(window) = window_weak.upgrade() {
+                background::toggle_opacity(&window);
+                for terminal in panes::terminals(window.upcast_ref()) {
+                    opts.config.apply_background(&terminal);
+                }
+            }
+        });
+        window.add_action(&toggle_opacity);
+
+        let close_window = SimpleAction::new("close-window", None);
+        let window_weak = window.downgrade();
+        close_window.connect_activate(move |_, _| {
+            if let Some(window) = window_weak.upgrade() {
+                window.close();
+            }
+        });
+        window.add_action(&close_window);
+
+        let first = Rc::new(TermOptions {
+            dump_on_exit: launch.dump_on_exit.clone(),
+            ..(*options).clone()
+        });
+        let terminal = Self::add_tab(&notebook, &first, &launch.command);
+        if let Some(path) = &launch.record {
+            let started = match options.terminals.stream_of(&terminal) {
+                Some(stream) => options.recordings.start(&terminal, &stream, path),
+                None => Err("the terminal is not registered".to_string()),
+            };
+            if let Err(err) = started {
+                eprintln!("Failed to start recording: {}", err);
+            }
+        }
+
+        // window.show();
+        window.present();
+
+        // if !command.is_empty() {
+        //     terminal.feed_child(format!("{}\n", &command)
+        //         .as_bytes());
+        // }
+
+        Some(window)
+    }
+
+    // Shows or hides the drop-down window, opening it the first time
+    fn toggle_dropdown(&self, app: &Application, launch: &Launch) {
+        let config = self.config();
+
+        if let Some(window) = self.dropdown.borrow().upgrade() {
+            if launch.toggle && window.is_visible() {
+                dropdown::hide(&window, config, &self.dropdown_slide);
+            } else {
+                dropdown::show(&window, app, config);
+            }
+            return;
+        }
 
-        let ini_file = self.ini_file.clone();
-        let (default_width, default_height) = self.window_size;
-        let login_shell = self.login_shell.clone();
-        let working_dir = self.working_dir.clone();
-        let command = self.command.clone();
+        let settings = config.ini().section(Some("Settings")).cloned().unwrap_or_default();
+        let launch = Launch {
+            window_size: Dropdown::from_settings(&settings).size(app),
+            ..launch.clone()
+        };
+        if let Some(window) = self.open_window(app, &launch) {
+            self.dropdown.replace(window.downgrade());
+        }
+    }
+
+    // Whether this instance restores and saves the session: one started
+    // with --restore or the unique instance of an --app-id, not every
+    // separate instance
+    fn owns_session(&self) -> bool {
+        self.restore || !self.app_id.is_empty()
+    }
+
+    // Opens the first windows: the last session if it is to be restored,
+    // or else the window of the command line
+    fn start(&self, app: &Application) {
+        let restore = self.restore || (self.owns_session() && self.config().ini()
+            .section(Some("Settings"))
+            .map(|settings| get_bool(settings, "restore_session", false))
+            .unwrap_or(false));
+
+        let fresh = self.launch.dropdown || self.launch.record.is_some() || self.launch.play.is_some();
+        if restore && !fresh && session::restore(self, app, &self.session_file()) {
+            return;
+        }
+        self.launch(app, &self.launch);
+    }
+
+    // Opens the window asked for by a command line
+    fn launch(&self, app: &Application, launch: &Launch) {
+        if let Some(options) = &launch.play {
+            play::open(app, self.config(), options);
+        } else if launch.dropdown {
+            self.toggle_dropdown(app, launch);
+        } else {
+            self.open_window(app, launch);
+        }
+    }
+
+    fn create(self) {
 
         // https://lazka.github.io/pgi-docs/Gio-2.0/flags.html
 
@@ -230,223 +1335,202 @@ impl AppTerm {
             // .flags(gio::ApplicationFlags::NON_UNIQUE)
             .build();
 
-        if self.app_id.is_empty() {
-            application.set_flags(gio::ApplicationFlags::NON_UNIQUE);
+        if self.app_id.is_empty() && self.launch.dropdown {
+            // a well-known ID, so that --toggle finds the drop-down window
+            application.set_application_id(Some(&format!("org.gtk_rs.{}", dropdown::APP_ID)));
+            application.set_flags(gio::ApplicationFlags::HANDLES_COMMAND_LINE);
+        } else if self.app_id.is_empty() {
+            // an ID for the notifications (which do not use the bus
+            // name), the instances stay separate
+            application.set_application_id(Some(&format!("org.gtk_rs.{}", APP_TITLE)));
+            application.set_flags(gio::ApplicationFlags::NON_UNIQUE | gio::ApplicationFlags::HANDLES_COMMAND_LINE);
         } else {
             // println!("Using application id: {}", &self.app_id);
             application.set_application_id(Some(&format!("org.gtk_rs.{}", self.app_id)));
+            application.set_flags(gio::ApplicationFlags::HANDLES_COMMAND_LINE);
         }
 
-        application.connect_activate(move |app| {
-
-            /*
-            let config = if ini_file.exists() {
-                match Ini::load_from_file(&ini_file) {
-                    Ok(config) => config,
-                    Err(err) => {
-                        panic!("failed to parse config file: {}", err);
-                    }
-                }
-            } else {
-                Self::default_ini(None)
-            };
-            */
+        let notification_backend = notify::select_backend();
 
-            let config = if ini_file.exists() {
-                Ini::load_from_file(&ini_file).unwrap_or_else(|err| {
-                    eprintln!("failed to parse config file: {}", err);
-                    Ini::new()
-                })
-            } else {
-                Self::default_ini(None)
-            };
+        let app_term = Rc::new(self);
 
-            let settings = match config.section(Some("Settings")) {
-                Some(section) => section,
-                None => {
-                    eprintln!("Error: No settings section found in config.");
-                    app.quit();
-                    return;
-                }
-            };
+        // Application wide actions
+        let this = app_term.clone();
+        application.connect_startup(move |app| {
+            notify::load_backend(app, notification_backend);
 
-            let win_width = if default_width == 0 {
-                settings
-                    .get("default_width")
-                    .and_then(|w| w.parse::<usize>().ok())
-                    .unwrap_or(DEFAULT_WIDTH)
-            } else {
-                default_width
-            }
-            .max(100); // Ensure the minimum width is 100
+            let ini = this.ini.take()
+                .unwrap_or_else(|| Self::load_config(&this.ini_file));
+            let config = Config::new(this.ini_file.clone(), ini, this.terminals.clone());
+            keybindings::apply(app, config.ini().section(Some("Keybindings")));
+            config.watch(app);
+            // startup runs only once, so the cell is still empty
+            let _ = this.config.set(config);
 
-            let win_height = if default_height == 0 {
-                settings
-                    .get("default_height")
-                    .and_then(|h| h.parse::<usize>().ok())
-                    .unwrap_or(DEFAULT_HEIGHT)
-            } else {
-                default_height
-            }
-            .max(100); // Ensure the minimum height is 100
+            remote::export(app, &this);
 
-            let window = ApplicationWindow::builder()
-                .application(app)
-                .title(APP_TITLE)
-                .default_width(win_width as i32)
-                .default_height(win_height as i32)
-                .width_request(win_width as i32)
-                .height_request(win_height as i32)
-                .build();
-
-            window.connect_destroy(|_| {
-                println!("Window destroyed.");
-            });
-
-            window.set_icon_name(settings.get("icon_name"));
-
-            let css_provider = CssProvider::new();
-
-            match settings.get("styles_file") {
-                Some(styles_file) => {
-                    css_provider.load_from_path(&styles_file);
-                },
-                None => {
-                    css_provider.load_from_data(Self::default_style().as_str());
-                }
-            };
-
-            let scrolled_window = ScrolledWindow::builder().build();
-            scrolled_window.set_policy(
-                gtk4::PolicyType::Never, 
-                gtk4::PolicyType::Automatic,
-            );
-
-            let sw_style_context = scrolled_window.style_context();
-            sw_style_context.add_class("scrolled-window");
-            sw_style_context.add_provider(&css_provider, gtk4::STYLE_PROVIDER_PRIORITY_APPLICATION);
-
-            let terminal = Self::make_terminal(
-                &login_shell, 
-                &working_dir, 
-                &command,
-            );
-
-            let term_style_context = terminal.style_context();
-            term_style_context.add_class("terminal");
-            term_style_context.add_provider(&css_provider, gtk4::STYLE_PROVIDER_PRIORITY_APPLICATION);
-
-            /*
-            let win = window.clone();
-            terminal.connect_window_title_changed(move |terminal| {
-                if let Some(title) = terminal.window_title() {
-                    // println!("Window title changed: {:?}", title);
-                    win.set_title(Some(&title));
+            let save_session = SimpleAction::new("save-session", None);
+            let app_weak = app.downgrade();
+            let app_term = this.clone();
+            save_session.connect_activate(move |_, _| {
+                if let Some(app) = app_weak.upgrade().filter(|_| app_term.owns_session()) {
+                    session::save(&app, &app_term.terminals, &app_term.session_file());
                 }
             });
-            */
-
-            // Update window title when terminal changes
-            let window_weak = window.downgrade();
-            terminal.connect_window_title_changed(move |term| {
-                if let Some(title) = term.window_title() {
-                    if let Some(window) = window_weak.upgrade() {
-                        // println!("Window title changed: {:?}", title);
-                        window.set_title(Some(&title));
+            app.add_action(&save_session);
+
+            // quit cleanly, saving the session, when logging out or killed
+            for signal in [libc::SIGHUP, libc::SIGINT, libc::SIGTERM] {
+                let app_weak = app.downgrade();
+                glib::unix_signal_add_local(signal, move || {
+                    if let Some(app) = app_weak.upgrade() {
+                        app.quit();
                     }
+                    glib::ControlFlow::Continue
+                });
+            }
+
+            let new_window = SimpleAction::new("new-window", None);
+            let app_weak = app.downgrade();
+            let app_term = this.clone();
+            new_window.connect_activate(move |_, _| {
+                if let Some(app) = app_weak.upgrade() {
+                    // open in the directory of the focused terminal
+                    let working_dir = app.active_window()
+                        .and_downcast::<ApplicationWindow>()
+                        .and_then(|window| {
+                            let notebook = Self::notebook_of(&window)?;
+                            Self::active_terminal(&window, &notebook)
+                        })
+                        .and_then(|terminal| cwd::of(&terminal))
+                        .unwrap_or_else(|| app_term.launch.working_dir.clone());
+                    let launch = Launch {
+                        command: String::new(),
+                        working_dir,
+                        dropdown: false,
+                        toggle: false,
+                        record: None,
+                        play: None,
+                        dump_on_exit: None,
+                        ..app_term.launch.clone()
+                    };
+                    app_term.open_window(&app, &launch);
                 }
             });
-
-            // quit the application
-            // let app_clone = app.clone();
-            // terminal.connect_eof(move |_terminal|{
-            //     app_clone.quit();
-            // });
-
-            // quit the application
-            // Close window on EOF
-            let window_weak = window.downgrade();
-            terminal.connect_eof(move |_| {
-                if let Some(window) = window_weak.upgrade() {
-                    window.close();
+            app.add_action(&new_window);
+
+            // Opens termint.ini in the default editor, creating it first
+            let preferences = SimpleAction::new("preferences", None);
+            let app_weak = app.downgrade();
+            let app_term = this.clone();
+            preferences.connect_activate(move |_, _| {
+                let Some(app) = app_weak.upgrade() else {
+                    return;
+                };
+                let ini_file = &app_term.ini_file;
+                let config_dir = ini_file.parent().map(Path::to_path_buf).unwrap_or_default();
+                if let Err(err) = Self::init_settings(&config_dir, ini_file) {
+                    eprintln!("Failed to create {}: {}", ini_file.display(), err);
+                    return;
+                }
+                let uri = gio::File::for_path(ini_file).uri();
+                match app.active_window() {
+                    Some(window) => links::open(&window, &uri),
+                    None => if let Err(err) = gio::AppInfo::launch_default_for_uri(&uri, None::<&gio::AppLaunchContext>) {
+                        eprintln!("Failed to open {}: {}", uri, err);
+                    },
                 }
             });
-
-            // this code is for testing purposes only
-            /*
-            let app_clone = app.clone();
-            // quit the application
-            terminal.connect_commit(move |_terminal, input, _s| {
-                unsafe {
-                    println!("Debug Input: {} Buffer: {:?}", input, BUFFER);
-                    if input == "\u{7f}" { // backspace
-                        if !BUFFER.is_empty() {
-                            BUFFER.pop();
-                        }
-                        return;
-                    }
-
-                    if BUFFER.len() < "exit\r".len() {
-                        BUFFER.push_str(input);
-                        if input == "\r" {
-                            if BUFFER.eq_ignore_ascii_case("exit\r") {
-                                println!("Debug Exit Input: {} Buffer: {:?}", input, BUFFER);
-                                app_clone.quit();
-                            }
-                        }
-                    }
-                    if input == "\r" {
-                        BUFFER.clear();
-                    }
+            app.add_action(&preferences);
+
+            // also reachable over D-Bus through org.gtk.Actions
+            let toggle_dropdown = SimpleAction::new("toggle-dropdown", None);
+            let app_weak = app.downgrade();
+            let app_term = this.clone();
+            toggle_dropdown.connect_activate(move |_, _| {
+                if let Some(app) = app_weak.upgrade() {
+                    let launch = Launch {
+                        command: String::new(),
+                        dropdown: true,
+                        toggle: true,
+                        record: None,
+                        play: None,
+                        dump_on_exit: None,
+                        ..app_term.launch.clone()
+                    };
+                    app_term.toggle_dropdown(&app, &launch);
                 }
             });
-            */
-
-            /*
-            // alternative approach
-            let buffer = Arc::new(Mutex::new(String::new()));
-            terminal.connect_commit(move |_terminal, input, _s| {
-                // println!("Debug Input: {} Buffer: {:?}", input, buffer);
-                if buffer.lock().unwrap().len() < "exit\r".len() {
-                    buffer.lock().unwrap().push_str(input);
-                    if input == "\r" {
-                        if buffer.lock().unwrap().eq_ignore_ascii_case("exit\r") {
-                            app_clone.quit();
-                        }
+            app.add_action(&toggle_dropdown);
+
+            // Brings back the terminal a notification was sent for
+            let focus_terminal = SimpleAction::new(notify::FOCUS_ACTION, Some(&u32::static_variant_type()));
+            let app_term = this.clone();
+            focus_terminal.connect_activate(move |_, parameter| {
+                let Some(terminal) = parameter
+                    .and_then(|parameter| parameter.get::<u32>())
+                    .and_then(|id| app_term.terminals.get(id)) else {
+                    return;
+                };
+                let Some(window) = terminal.root().and_downcast::<ApplicationWindow>() else {
+                    return;
+                };
+                if let Some(notebook) = Self::notebook_of(&window) {
+                    if let Some(page_num) = Self::page_of(&notebook, &terminal).and_then(|page| notebook.page_num(&page)) {
+                        notebook.set_current_page(Some(page_num));
                     }
                 }
-                if input == "\r" {
-                    buffer.lock().unwrap().clear();
-                }
+                window.present();
+                terminal.grab_focus();
             });
-            */
+            app.add_action(&focus_terminal);
+        });
 
-            // 1. Middle-Click to Paste (Linux Primary Selection)
-            let terminal_middle_click = terminal.clone();
-            let middle_gesture = gtk4::GestureClick::new();
-            middle_gesture.set_button(2); // Button 2 is the Middle Mouse Button
+        // app.quit() leaves the windows open, save them before they go
+        let this = app_term.clone();
+        application.connect_shutdown(move |app| {
+            if this.owns_session() {
+                session::save(app, &this.terminals, &this.session_file());
+            }
+        });
 
-            middle_gesture.connect_pressed(move |_, _, _, _| {
-                // This pastes from the PRIMARY selection (what you just highlighted)
-                terminal_middle_click.paste_primary();
-            });
-            terminal.add_controller(middle_gesture);
+        let this = app_term.clone();
+        application.connect_activate(move |app| {
+            this.start(app);
+        });
 
-            // 2. Auto-copy on Highlight
-            // This ensures that as soon as you select text, it's ready for middle-click
-            terminal.connect_selection_changed(|term| {
-                term.copy_primary();
-            });
+        // A second "termint -a <id> ..." runs in the first instance: every
+        // invocation opens its own window with its own options
+        application.connect_command_line(move |app, cmdline| {
+            if !cmdline.is_remote() {
+                app_term.start(app);
+                return 0;
+            }
 
-            scrolled_window.set_child(Some(&terminal));
-            window.set_child(Some(&scrolled_window));
-            // window.show();
-            window.present();
+            // help, version and errors go back to the terminal of the invocation
+            let matches = match cli().try_get_matches_from(cmdline.arguments()) {
+                Ok(matches) => matches,
+                Err(err) => {
+                    print_remote(cmdline, &err.render().to_string(), err.use_stderr());
+                    return err.exit_code();
+                }
+            };
 
-            // if !command.is_empty() {
-            //     terminal.feed_child(format!("{}\n", &command)
-            //         .as_bytes());
-            // }
+            let cwd = cmdline.cwd().unwrap_or_default();
+            let shell = cmdline.getenv("SHELL").map(String::from);
+            let config = app_term.config();
+            let launch = Launch::from_matches(&matches, &cwd, shell, &config.ini());
+
+            match launch {
+                Ok(launch) => {
+                    app_term.launch(app, &launch);
+                    0
+                }
+                Err(err) => {
+                    print_remote(cmdline, &format!("{}\n", err), true);
+                    1
+                }
+            }
         });
 
         // Use application.run_with_args(&[]) if your app is a sub-component
@@ -459,15 +1543,11 @@ impl AppTerm {
         application.run();
     }
 
-    fn new(
-        app_id: Option<&String>,
-        login_shell: Option<&PathBuf>,
-        working_dir: Option<&PathBuf>,
-        window_size: Option<&String>,
-        custom_config_dir: Option<&PathBuf>,
-        create_default_settings: Option<&bool>,
-        command: Option<&String>,
-    ) -> Self {
+    fn new(matches: &ArgMatches) -> Self {
+
+        let app_id = matches.get_one::<String>("app_id");
+        let custom_config_dir = matches.get_one::<PathBuf>("directory");
+        let create_default_settings = matches.get_one::<bool>("init_settings");
 
         let config_dir = if let Some(dir) = custom_config_dir {
             dir.join(APP_NAME)
@@ -480,52 +1560,29 @@ impl AppTerm {
         let ini_file = config_dir.join(format!("{}.ini", APP_NAME));
 
         if *create_default_settings.unwrap_or(&false) {
-            if !config_dir.is_dir() {
-                fs::create_dir_all(&config_dir)
-                    .expect("failed to create configuration directory");
-            }
-
-            if !ini_file.exists() {
-                // Self::default_ini_file(&config_dir);
-                let conf = Self::default_ini(Some(&config_dir));
-                conf.write_to_file(&ini_file).unwrap();
-            }
+            Self::init_settings(&config_dir, &ini_file)
+                .expect("failed to create the default settings");
         }
 
+        let ini = Self::load_config(&ini_file);
+
         AppTerm {
             app_id: app_id.cloned().unwrap_or_default(),
             ini_file,
-            login_shell: login_shell
-                .map(|s| {
-                    if !s.is_file() {
-                        panic!("The specified login shell does not exist: {}", s.display());
-                    }
-                    s.to_string_lossy().to_string()
-                })
-                .unwrap_or_else(|| {
-                    env::var("SHELL").expect("Unable to get the user's shell")
-                }),
-            command: command.cloned().unwrap_or_default(),
-            working_dir: working_dir
-                .map(|d| {
-                    if !d.is_dir() {
-                        panic!("The specified working directory does not exist: {}", d.display());
-                    }
-                    d.to_string_lossy().to_string()
-                })
-                .unwrap_or_default(),
-            window_size: window_size
-                .map(|s| {
-                    let parts: Vec<&str> = s.split('x').collect();
-                    if parts.len() != 2 {
-                        panic!("Invalid window size: {}", s);
-                    }
-                    (
-                        parts[0].parse::<usize>().unwrap_or(DEFAULT_WIDTH),
-                        parts[1].parse::<usize>().unwrap_or(DEFAULT_HEIGHT),
-                    )
-                })
-                .unwrap_or((0, 0)),
+            launch: Launch::from_matches(
+                matches,
+                &env::current_dir().unwrap_or_default(),
+                env::var("SHELL").ok(),
+                &ini,
+            )
+                .unwrap_or_else(|err| panic!("{}", err)),
+            terminals: Rc::default(),
+            recordings: Rc::default(),
+            dropdown: RefCell::new(WeakRef::new()),
+            dropdown_slide: Rc::default(),
+            ini: RefCell::new(Some(ini)),
+            restore: matches.get_flag("restore"),
+            config: OnceCell::new(),
         }
     }
 }
@@ -539,11 +1596,25 @@ impl AppTerm {
 //     println!("=============================");
 // }
 
-fn main() {
-
-    // print_environment();
+// Prints on the terminal of an invocation forwarded to the first
+// instance (the _literal variants need GLib 2.80)
+fn print_remote(cmdline: &ApplicationCommandLine, message: &str, stderr: bool) {
+    let Ok(message) = CString::new(message.replace('\0', "")) else {
+        return;
+    };
+    unsafe {
+        if stderr {
+            gio::ffi::g_application_command_line_printerr(cmdline.to_glib_none().0, c"%s".as_ptr(), message.as_ptr());
+        } else {
+            gio::ffi::g_application_command_line_print(cmdline.to_glib_none().0, c"%s".as_ptr(), message.as_ptr());
+        }
+    }
+}
 
-    let matches = Command::new(APP_NAME)
+// The command line, also parsed for the invocations forwarded to the
+// first instance
+fn cli() -> Command {
+    Command::new(APP_NAME)
         .version(VERSION)
         .about("Minimal terminal emulator with mint flavor!")
         .arg(
@@ -596,15 +1667,108 @@ fn main() {
                 .long("window-size-pixels")
                 .value_parser(value_parser!(String)),
         )
-        .get_matches();
-
-    AppTerm::new(
-        matches.get_one::<String>("app_id"),
-        matches.get_one::<PathBuf>("login_shell"),
-        matches.get_one::<PathBuf>("working_directory"),
-        matches.get_one::<String>("window_size_pixels"),
-        matches.get_one::<PathBuf>("directory"),
-        matches.get_one::<bool>("init_settings"),
-        matches.get_one::<String>("execute"),
-    ).create();
-}
\ No newline at end of file
+        .arg(
+            Arg::new("env")
+                .help("Set an environment variable (KEY=VALUE) for the shell, can be repeated")
+                .long("env")
+                .value_name("KEY=VALUE")
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(String)),
+        )
+        .arg(
+            Arg::new("clean_env")
+                .help("Start the shell with a clean environment")
+                .long("clean-env")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("hold")
+                .help("Keep the terminal open after the command exits (like xterm -hold)")
+                .long("hold")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("profile")
+                .help("Use the settings of a [Profile:NAME] section")
+                .short('p')
+                .long("profile")
+                .value_name("NAME")
+                .value_parser(value_parser!(String)),
+        )
+        .arg(
+            Arg::new("record")
+                .help("Record the terminal to an asciicast v2 file")
+                .long("record")
+                .value_name("FILE.cast")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("dump_on_exit")
+                .help("Save the scrollback to FILE when the command exits (HTML for .html)")
+                .long("dump-on-exit")
+                .value_name("FILE")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("play")
+                .help("Play an asciicast v2 recording")
+                .long("play")
+                .value_name("FILE.cast")
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("speed")
+                .help("Playback speed of --play")
+                .long("speed")
+                .value_name("N")
+                .requires("play")
+                .value_parser(value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("idle_limit")
+                .help("Shorten the pauses of --play to at most S seconds")
+                .long("idle-limit")
+                .value_name("S")
+                .requires("play")
+                .value_parser(value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("shell_integration")
+                .help("Print the shell code that reports the current directory to termint")
+                .long("shell-integration")
+                .value_name("SHELL")
+                .value_parser(cwd::SHELLS),
+        )
+        .arg(
+            Arg::new("restore")
+                .help("Reopen the windows of the last session")
+                .long("restore")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("dropdown")
+                .help("Show a drop-down terminal at the top of the screen")
+                .long("dropdown")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("toggle")
+                .help("Show or hide the drop-down terminal of the running instance")
+                .long("toggle")
+                .action(ArgAction::SetTrue),
+        )
+}
+
+fn main() {
+
+    // print_environment();
+
+    let matches = cli().get_matches();
+
+    if let Some(shell) = matches.get_one::<String>("shell_integration") {
+        print!("{}", cwd::shell_integration(shell).expect("the shell was validated by clap"));
+        return;
+    }
+
+    AppTerm::new(&matches).create();
+}
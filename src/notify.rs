@@ -0,0 +1,254 @@
+//
+// Desktop notifications
+//
+// While its window is not focused, a terminal sends a notification when
+// the bell rings, when its child exits and when a long command finishes.
+// Clicking the notification brings the terminal back. Read from the
+// [Settings] section of termint.ini:
+//
+// notify_bell = true
+// notify_exit = true
+// notify_command = true
+// notify_command_time = 10    (seconds a command must run)
+// notify_silence = 0          (seconds of quiet that end it, 0 is off)
+//
+// A command runs from the Enter that starts it to the next prompt of the
+// shell, which the shell integration of cwd.rs marks with OSC 7, as VTE
+// reports. For shells without it, notify_silence counts a
+// terminal that printed for notify_command_time seconds and then went
+// quiet for that long as a finished command.
+//
+// Outside a sandbox the notifications go through the freedesktop backend
+// of GLib, which activates the click in the process that sent them: the
+// instances without --app-id do not own their application ID on the
+// bus. In a sandbox (Flatpak, Snap, a container) GLib keeps the portal.
+//
+
+use std::{
+    cell::Cell,
+    env,
+    path::Path,
+    process,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use gtk4::{
+    gio,
+    glib,
+    prelude::*,
+    Application,
+    Window,
+};
+
+use ini::Properties;
+
+use vte4::{
+    Terminal,
+    TerminalExt,
+};
+
+use crate::{
+    config::Config,
+    get_bool,
+    on_exit,
+    APP_TITLE,
+};
+
+// The app action that the notifications activate with a terminal ID
+pub const FOCUS_ACTION: &str = "focus-terminal";
+
+const DEFAULT_COMMAND_TIME: u64 = 10;
+const DEFAULT_SILENCE: u64 = 0;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// Where GLib looks for the name of the notification backend
+const BACKEND_VAR: &str = "GNOTIFICATION_BACKEND";
+
+// Whether termint runs where GLib sends the notifications to a portal
+fn sandboxed() -> bool {
+    Path::new("/.flatpak-info").exists()
+        || env::var_os("SNAP").is_some()
+        || env::var_os("container").is_some()
+        || env::var("GTK_USE_PORTAL").is_ok_and(|value| value == "1")
+}
+
+// Picks the freedesktop backend, unless the user chose one or termint
+// is sandboxed, before the application starts; returns true if it did
+pub fn select_backend() -> bool {
+    if env::var_os(BACKEND_VAR).is_some() || sandboxed() {
+        return false;
+    }
+    env::set_var(BACKEND_VAR, "freedesktop");
+    true
+}
+
+// Loads the backend picked by select_backend and takes the variable out
+// of the environment before the first terminal is spawned, so that the
+// shells do not inherit it
+pub fn load_backend(app: &Application, selected: bool) {
+    if !selected {
+        return;
+    }
+    // GLib loads the backend the first time it is used
+    app.withdraw_notification("terminal-none");
+    env::remove_var(BACKEND_VAR);
+}
+
+pub fn default_settings(settings: &mut ini::SectionSetter) {
+    settings
+        .set("notify_bell", "true")
+        .set("notify_exit", "true")
+        .set("notify_command", "true")
+        .set("notify_command_time", DEFAULT_COMMAND_TIME.to_string())
+        .set("notify_silence", DEFAULT_SILENCE.to_string());
+}
+
+fn seconds(settings: &Properties, key: &str, default: u64) -> Duration {
+    let seconds = match settings.get(key).map(str::trim) {
+        Some(value) => value.parse::<u64>().unwrap_or_else(|_| {
+            eprintln!("Warning: invalid value \"{}\" for \"{}\"", value, key);
+            default
+        }),
+        None => default,
+    };
+    Duration::from_secs(seconds)
+}
+
+fn enabled(config: &Config, key: &str) -> bool {
+    config.ini()
+        .section(Some("Settings"))
+        .map(|settings| get_bool(settings, key, true))
+        .unwrap_or(true)
+}
+
+// Sends a notification for a terminal whose window is in the background
+fn send(terminal: &Terminal, id: u32, body: &str) {
+    let Some(window) = terminal.root().and_downcast::<Window>() else {
+        return;
+    };
+    if window.is_active() {
+        return;
+    }
+    let Some(app) = window.application() else {
+        return;
+    };
+
+    let title = terminal.window_title()
+        .map(|title| title.to_string())
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| APP_TITLE.to_string());
+
+    let notification = gio::Notification::new(&title);
+    notification.set_body(Some(body));
+    notification.set_default_action_and_target_value(
+        &format!("app.{}", FOCUS_ACTION),
+        Some(&id.to_variant()),
+    );
+    // a newer event of the same terminal replaces the last one, the
+    // process ID keeps apart the terminals of other instances
+    app.send_notification(Some(&format!("terminal-{}-{}", process::id(), id)), &notification);
+}
+
+// Sends a notification for a command that ran for `busy`, if it is on
+// and the command ran long enough
+fn command_finished(terminal: &Terminal, id: u32, config: &Config, busy: Duration) {
+    let settings = config.ini().section(Some("Settings")).cloned().unwrap_or_default();
+    if get_bool(&settings, "notify_command", true)
+        && busy >= seconds(&settings, "notify_command_time", DEFAULT_COMMAND_TIME) {
+        send(terminal, id, &format!("A command finished after {} s", busy.as_secs()));
+    }
+}
+
+// What was seen of a terminal, to tell when a command finished
+struct Activity {
+    // the Enter that started the command
+    command: Cell<Option<Instant>>,
+    // the shell marks its prompts, no need to wait for silence
+    prompts: Cell<bool>,
+    // the first and last output of the current burst
+    start: Cell<Option<Instant>>,
+    last: Cell<Instant>,
+}
+
+// Connects the notifications of a terminal with the ID `id`. Call it
+// before the child-exited handler that may close the terminal.
+pub fn setup(terminal: &Terminal, id: u32, config: &Rc<Config>) {
+    let config_weak = Rc::downgrade(config);
+    terminal.connect_bell(move |term| {
+        if config_weak.upgrade().is_some_and(|config| enabled(&config, "notify_bell")) {
+            send(term, id, "The bell rang");
+        }
+    });
+
+    let config_weak = Rc::downgrade(config);
+    terminal.connect_child_exited(move |term, status| {
+        if config_weak.upgrade().is_some_and(|config| enabled(&config, "notify_exit")) {
+            send(term, id, &format!("The process {}", on_exit::describe_status(status)));
+        }
+    });
+
+    let activity = Rc::new(Activity {
+        command: Cell::new(None),
+        prompts: Cell::new(false),
+        start: Cell::new(None),
+        last: Cell::new(Instant::now()),
+    });
+
+    let command = activity.clone();
+    terminal.connect_commit(move |_, text, _| {
+        if command.command.get().is_none() && text.contains('\r') {
+            command.command.set(Some(Instant::now()));
+        }
+    });
+
+    // VTE reports every OSC 7, even when the directory stays the same
+    let prompt = activity.clone();
+    let config_weak = Rc::downgrade(config);
+    terminal.connect_current_directory_uri_changed(move |term| {
+        prompt.prompts.set(true);
+        let Some(start) = prompt.command.take() else {
+            return;
+        };
+        if let Some(config) = config_weak.upgrade() {
+            command_finished(term, id, &config, start.elapsed());
+        }
+    });
+
+    let config_weak = Rc::downgrade(config);
+    terminal.connect_contents_changed(move |term| {
+        let now = Instant::now();
+        activity.last.set(now);
+        if activity.start.get().is_some() || activity.prompts.get() {
+            return;
+        }
+        let Some(config) = config_weak.upgrade() else {
+            return;
+        };
+        let settings = config.ini().section(Some("Settings")).cloned().unwrap_or_default();
+        if seconds(&settings, "notify_silence", DEFAULT_SILENCE).is_zero() {
+            return;
+        }
+        activity.start.set(Some(now));
+
+        // wait for the output to stop
+        let activity = activity.clone();
+        let config_weak = config_weak.clone();
+        let term = term.downgrade();
+        glib::timeout_add_local(CHECK_INTERVAL, move || {
+            let (Some(config), Some(term), Some(start)) = (config_weak.upgrade(), term.upgrade(), activity.start.get()) else {
+                return glib::ControlFlow::Break;
+            };
+            let settings = config.ini().section(Some("Settings")).cloned().unwrap_or_default();
+            let last = activity.last.get();
+            if last.elapsed() < seconds(&settings, "notify_silence", DEFAULT_SILENCE) {
+                return glib::ControlFlow::Continue;
+            }
+
+            activity.start.set(None);
+            command_finished(&term, id, &config, last.duration_since(start));
+            glib::ControlFlow::Break
+        });
+    });
+}
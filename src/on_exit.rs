@@ -0,0 +1,47 @@
+//
+// What happens to a terminal when its child process exits
+//
+// on_exit = close    close the pane (the default)
+// on_exit = hold     keep the output and show the exit status
+// on_exit = restart  start the shell or command again
+//
+
+use std::time::Duration;
+
+use ini::Properties;
+
+// A child that exits sooner than this is not restarted, so a command
+// that keeps failing does not spin
+pub const MIN_RUNTIME: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OnExit {
+    Close,
+    Hold,
+    Restart,
+}
+
+impl OnExit {
+
+    pub fn from_settings(settings: Option<&Properties>) -> Self {
+        match settings.and_then(|s| s.get("on_exit")).map(str::trim) {
+            Some("close") | None => OnExit::Close,
+            Some("hold") => OnExit::Hold,
+            Some("restart") => OnExit::Restart,
+            Some(value) => {
+                eprintln!("Warning: invalid value \"{}\" for \"on_exit\"", value);
+                OnExit::Close
+            }
+        }
+    }
+}
+
+// Describes a wait status as returned by waitpid()
+pub fn describe_status(status: i32) -> String {
+    let signal = status & 0x7f;
+    if signal == 0 {
+        format!("exited with status {}", (status >> 8) & 0xff)
+    } else {
+        format!("was killed by signal {}", signal)
+    }
+}
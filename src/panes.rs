@@ -0,0 +1,353 @@
+//
+// Split panes
+//
+// Every notebook page is a Box holding a tree of nested Paned
+// containers. The leaves of the tree are the panes: a ScrolledWindow
+// wrapping one terminal.
+//
+
+use gtk4::{
+    prelude::*,
+    Orientation,
+    Paned,
+    ScrolledWindow,
+    Widget,
+};
+
+use vte4::Terminal;
+
+// How far (in pixels) a pane divider moves per resize step
+const RESIZE_STEP: i32 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl Direction {
+
+    pub const ALL: [(&'static str, Direction); 4] = [
+        ("left", Direction::Left),
+        ("right", Direction::Right),
+        ("up", Direction::Up),
+        ("down", Direction::Down),
+    ];
+
+    fn orientation(self) -> Orientation {
+        match self {
+            Direction::Left | Direction::Right => Orientation::Horizontal,
+            Direction::Up | Direction::Down => Orientation::Vertical,
+        }
+    }
+}
+
+// The pane (scrolled window) that holds a terminal
+pub fn pane_of(terminal: &Terminal) -> Option<Widget> {
+    terminal.ancestor(ScrolledWindow::static_type())
+}
+
+// All terminals inside a widget tree, in depth-first order
+pub fn terminals(widget: &Widget) -> Vec<Terminal> {
+    let mut found = Vec::new();
+    collect_terminals(widget, &mut found);
+    found
+}
+
+fn collect_terminals(widget: &Widget, found: &mut Vec<Terminal>) {
+    if let Some(terminal) = widget.downcast_ref::<Terminal>() {
+        found.push(terminal.clone());
+        return;
+    }
+    let mut child = widget.first_child();
+    while let Some(widget) = child {
+        collect_terminals(&widget, found);
+        child = widget.next_sibling();
+    }
+}
+
+// Puts `new_pane` next to `pane`, sharing its space through a new Paned
+pub fn split(pane: &Widget, new_pane: &Widget, orientation: Orientation) {
+    let Some(parent) = pane.parent() else {
+        return;
+    };
+
+    // the new split always gets the whole pane
+    if let Some(page) = page_of(pane) {
+        unzoom(&page);
+    }
+
+    let size = match orientation {
+        Orientation::Horizontal => pane.width(),
+        _ => pane.height(),
+    };
+
+    let paned = Paned::builder()
+        .orientation(orientation)
+        .hexpand(true)
+        .vexpand(true)
+        .shrink_start_child(false)
+        .shrink_end_child(false)
+        .build();
+
+    replace(&parent, pane, &paned);
+    paned.set_start_child(Some(pane));
+    paned.set_end_child(Some(new_pane));
+
+    if size > 0 {
+        paned.set_position(size / 2);
+    }
+}
+
+// Removes a pane and lets its sibling take over the space of the split.
+// Returns false if the pane is the only one on its page.
+pub fn close(pane: &Widget) -> bool {
+    let Some(paned) = pane.parent().and_downcast::<Paned>() else {
+        return false;
+    };
+
+    if let Some(page) = page_of(pane) {
+        unzoom(&page);
+    }
+
+    let sibling = if paned.start_child().as_ref() == Some(pane) {
+        paned.end_child()
+    } else {
+        paned.start_child()
+    };
+
+    paned.set_start_child(None::<&Widget>);
+    paned.set_end_child(None::<&Widget>);
+
+    if let (Some(sibling), Some(parent)) = (sibling, paned.parent()) {
+        replace(&parent, paned.upcast_ref(), &sibling);
+        if let Some(terminal) = terminals(&sibling).first() {
+            terminal.grab_focus();
+        }
+    }
+
+    true
+}
+
+// Swaps `old` for `new` in the parent container (a Paned or the page Box)
+fn replace(parent: &Widget, old: &Widget, new: &impl IsA<Widget>) {
+    if let Some(paned) = parent.downcast_ref::<Paned>() {
+        if paned.start_child().as_ref() == Some(old) {
+            paned.set_start_child(None::<&Widget>);
+            paned.set_start_child(Some(new));
+        } else {
+            paned.set_end_child(None::<&Widget>);
+            paned.set_end_child(Some(new));
+        }
+    } else if let Some(page) = parent.downcast_ref::<gtk4::Box>() {
+        page.remove(old);
+        page.append(new);
+    }
+}
+
+// The page Box at the root of the pane tree
+fn page_of(widget: &Widget) -> Option<Widget> {
+    let mut current = widget.parent();
+    while let Some(widget) = current {
+        if widget.is::<gtk4::Box>() {
+            return Some(widget);
+        }
+        current = widget.parent();
+    }
+    None
+}
+
+// Moves the focus to the nearest terminal in the given direction
+pub fn focus_neighbour(terminal: &Terminal, direction: Direction) {
+    let Some(page) = pane_of(terminal).and_then(|pane| page_of(&pane)) else {
+        return;
+    };
+    let Some(current) = terminal.compute_bounds(&page) else {
+        return;
+    };
+
+    let (cx, cy) = (current.x() + current.width() / 2.0, current.y() + current.height() / 2.0);
+
+    let nearest = terminals(&page)
+        .into_iter()
+        .filter(|other| other != terminal && other.is_drawable())
+        .filter_map(|other| {
+            let bounds = other.compute_bounds(&page)?;
+            let (ox, oy) = (bounds.x() + bounds.width() / 2.0, bounds.y() + bounds.height() / 2.0);
+            // distance along the direction and across it
+            let (along, across) = match direction {
+                Direction::Left => (cx - ox, (cy - oy).abs()),
+                Direction::Right => (ox - cx, (cy - oy).abs()),
+                Direction::Up => (cy - oy, (cx - ox).abs()),
+                Direction::Down => (oy - cy, (cx - ox).abs()),
+            };
+            (along > 0.0).then_some((along + across, other))
+        })
+        .min_by(|(a, _), (b, _)| a.total_cmp(b));
+
+    if let Some((_, other)) = nearest {
+        other.grab_focus();
+    }
+}
+
+// Moves the divider of the closest split with a matching orientation
+pub fn resize(terminal: &Terminal, direction: Direction) {
+    let Some(mut child) = pane_of(terminal) else {
+        return;
+    };
+    while let Some(parent) = child.parent() {
+        if let Some(paned) = parent.downcast_ref::<Paned>() {
+            if paned.orientation() == direction.orientation() {
+                let step = match direction {
+                    Direction::Left | Direction::Up => -RESIZE_STEP,
+                    Direction::Right | Direction::Down => RESIZE_STEP,
+                };
+                paned.set_position(
+                    (paned.position() + step).clamp(paned.min_position(), paned.max_position()),
+                );
+                return;
+            }
+        }
+        child = parent;
+    }
+}
+
+// Shows only the pane of this terminal, or every pane again if the page
+// is already zoomed
+pub fn toggle_zoom(terminal: &Terminal) {
+    let Some(pane) = pane_of(terminal) else {
+        return;
+    };
+    let Some(page) = page_of(&pane) else {
+        return;
+    };
+
+    if is_zoomed(&page) {
+        unzoom(&page);
+    } else {
+        let mut child = pane;
+        while let Some(parent) = child.parent() {
+            if let Some(paned) = parent.downcast_ref::<Paned>() {
+                let sibling = if paned.start_child().as_ref() == Some(&child) {
+                    paned.end_child()
+                } else {
+                    paned.start_child()
+                };
+                if let Some(sibling) = sibling {
+                    sibling.set_visible(false);
+                }
+            }
+            child = parent;
+        }
+    }
+
+    terminal.grab_focus();
+}
+
+fn is_zoomed(widget: &Widget) -> bool {
+    if let Some(paned) = widget.downcast_ref::<Paned>() {
+        let hidden = [paned.start_child(), paned.end_child()]
+            .iter()
+            .flatten()
+            .any(|child| !child.is_visible());
+        if hidden {
+            return true;
+        }
+    }
+    let mut child = widget.first_child();
+    while let Some(widget) = child {
+        if is_zoomed(&widget) {
+            return true;
+        }
+        child = widget.next_sibling();
+    }
+    false
+}
+
+fn unzoom(widget: &Widget) {
+    if let Some(paned) = widget.downcast_ref::<Paned>() {
+        for child in [paned.start_child(), paned.end_child()].iter().flatten() {
+            child.set_visible(true);
+            unzoom(child);
+        }
+        return;
+    }
+    let mut child = widget.first_child();
+    while let Some(widget) = child {
+        unzoom(&widget);
+        child = widget.next_sibling();
+    }
+}
+
+// The shape of a pane tree, written as h(...,...) and v(...,...) around
+// the panes ("p"), e.g. h(p,v(p,p)). The terminals of a layout are
+// taken in depth-first order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Layout {
+    Pane,
+    Split(Orientation, Box<Layout>, Box<Layout>),
+}
+
+impl Layout {
+
+    // The layout of a page, or of any widget of its pane tree
+    pub fn of(widget: &Widget) -> Option<Layout> {
+        if widget.is::<ScrolledWindow>() {
+            return Some(Layout::Pane);
+        }
+        if let Some(paned) = widget.downcast_ref::<Paned>() {
+            return match (paned.start_child(), paned.end_child()) {
+                (Some(start), Some(end)) => Some(Layout::Split(
+                    paned.orientation(),
+                    Box::new(Self::of(&start)?),
+                    Box::new(Self::of(&end)?),
+                )),
+                (Some(child), None) | (None, Some(child)) => Self::of(&child),
+                (None, None) => None,
+            };
+        }
+        Self::of(&widget.first_child()?)
+    }
+
+    pub fn parse(text: &str) -> Option<Layout> {
+        let (layout, rest) = Self::parse_node(text.trim())?;
+        rest.is_empty().then_some(layout)
+    }
+
+    fn parse_node(text: &str) -> Option<(Layout, &str)> {
+        if let Some(rest) = text.strip_prefix('p') {
+            return Some((Layout::Pane, rest));
+        }
+        let orientation = match text.get(..2)? {
+            "h(" => Orientation::Horizontal,
+            "v(" => Orientation::Vertical,
+            _ => return None,
+        };
+        let (start, rest) = Self::parse_node(&text[2..])?;
+        let (end, rest) = Self::parse_node(rest.strip_prefix(',')?)?;
+        let rest = rest.strip_prefix(')')?;
+        Some((Layout::Split(orientation, Box::new(start), Box::new(end)), rest))
+    }
+
+    // The number of panes
+    pub fn panes(&self) -> usize {
+        match self {
+            Layout::Pane => 1,
+            Layout::Split(_, start, end) => start.panes() + end.panes(),
+        }
+    }
+}
+
+impl std::fmt::Display for Layout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Layout::Pane => write!(f, "p"),
+            Layout::Split(orientation, start, end) => {
+                let prefix = if *orientation == Orientation::Horizontal { "h" } else { "v" };
+                write!(f, "{}({},{})", prefix, start, end)
+            }
+        }
+    }
+}
@@ -0,0 +1,12 @@
+//
+// PCRE2 compile flags for vte4::Regex (from pcre2.h)
+//
+
+pub const CASELESS: u32 = 0x0000_0008;
+pub const MULTILINE: u32 = 0x0000_0400;
+pub const UCP: u32 = 0x0002_0000;
+pub const UTF: u32 = 0x0008_0000;
+pub const NO_UTF_CHECK: u32 = 0x4000_0000;
+
+// The flags VTE expects on every regex it is given
+pub const DEFAULT: u32 = UTF | NO_UTF_CHECK | UCP | MULTILINE;
@@ -0,0 +1,279 @@
+//
+// Playback of asciicast recordings
+//
+// `termint --play FILE.cast [--speed N] [--idle-limit S]` opens a window
+// that feeds the recorded output into a terminal, with no child process,
+// at the recorded pace. Pauses longer than --idle-limit (or the
+// idle_time_limit of the file) are shortened to it.
+//
+// Space       pause / resume
+// Left/Right  seek 5 seconds back / forward
+// Home/End    go to the start / end
+// q           close the window
+//
+
+use std::{
+    cell::Cell,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use gtk4::{
+    gdk,
+    glib,
+    prelude::*,
+    Application,
+    ApplicationWindow,
+    EventControllerKey,
+    PropagationPhase,
+};
+
+use vte4::{
+    Terminal,
+    TerminalExt,
+};
+
+use crate::{
+    asciicast::{self, Event},
+    config::Config,
+    AppTerm,
+    APP_TITLE,
+};
+
+const TICK: Duration = Duration::from_millis(15);
+const SEEK_STEP: f64 = 5.0;
+
+// What --play was given
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub path: PathBuf,
+    pub speed: f64,
+    pub idle_limit: Option<f64>,
+}
+
+struct Player {
+    name: String,
+    terminal: Terminal,
+    window: glib::WeakRef<ApplicationWindow>,
+    size: (i64, i64),
+    // times with the idle limit applied
+    events: Vec<(f64, Event)>,
+    duration: f64,
+    speed: f64,
+    next: Cell<usize>,
+    position: Cell<f64>,
+    last_tick: Cell<Instant>,
+    paused: Cell<bool>,
+    shown_second: Cell<i64>,
+}
+
+// Shortens the pauses between events to `limit`
+fn limit_idle(events: Vec<(f64, Event)>, limit: Option<f64>) -> Vec<(f64, Event)> {
+    let mut last = 0.0;
+    let mut time = 0.0;
+    events.into_iter()
+        .map(|(at, event)| {
+            let pause = (at - last).max(0.0);
+            last = at;
+            time += limit.map_or(pause, |limit| pause.min(limit));
+            (time, event)
+        })
+        .collect()
+}
+
+impl Player {
+
+    // Resizes the terminal and its window to a recorded size
+    fn resize(&self, columns: i64, rows: i64) {
+        self.terminal.set_size(columns, rows);
+        if let Some(window) = self.window.upgrade() {
+            window.set_default_size(
+                (columns * self.terminal.char_width()) as i32,
+                (rows * self.terminal.char_height()) as i32,
+            );
+        }
+    }
+
+    // Feeds the events up to the current position
+    fn play_until_position(&self) {
+        let position = self.position.get();
+        let mut next = self.next.get();
+        while let Some((time, event)) = self.events.get(next) {
+            if *time > position {
+                break;
+            }
+            match event {
+                Event::Output(data) => self.terminal.feed(data.as_bytes()),
+                Event::Resize(columns, rows) => self.resize(*columns, *rows),
+            }
+            next += 1;
+        }
+        self.next.set(next);
+    }
+
+    fn tick(&self) {
+        let now = Instant::now();
+        if !self.paused.get() {
+            let elapsed = now.duration_since(self.last_tick.get()).as_secs_f64();
+            self.position.set((self.position.get() + elapsed * self.speed).min(self.duration));
+        }
+        self.last_tick.set(now);
+        self.play_until_position();
+        self.update_title(false);
+    }
+
+    fn seek(&self, position: f64) {
+        let position = position.clamp(0.0, self.duration);
+        // going back replays the recording from the start
+        if position < self.position.get() {
+            self.terminal.reset(true, true);
+            self.resize(self.size.0, self.size.1);
+            self.next.set(0);
+        }
+        self.position.set(position);
+        self.play_until_position();
+        self.update_title(true);
+    }
+
+    fn toggle_pause(&self) {
+        // at the end, play again from the start
+        if self.position.get() >= self.duration {
+            self.seek(0.0);
+            self.paused.set(false);
+        } else {
+            self.paused.set(!self.paused.get());
+        }
+        self.update_title(true);
+    }
+
+    fn update_title(&self, force: bool) {
+        let second = self.position.get() as i64;
+        if !force && second == self.shown_second.get() {
+            return;
+        }
+        self.shown_second.set(second);
+        let state = if self.position.get() >= self.duration {
+            " (finished)"
+        } else if self.paused.get() {
+            " (paused)"
+        } else {
+            ""
+        };
+        if let Some(window) = self.window.upgrade() {
+            window.set_title(Some(&format!(
+                "{} — {} {}/{}s{}",
+                APP_TITLE, self.name, second, self.duration.ceil() as i64, state,
+            )));
+        }
+    }
+}
+
+fn name_of(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+// Opens a window playing a recording
+pub fn open(app: &Application, config: &Rc<Config>, options: &Options) {
+    let (header, events) = match asciicast::read(&options.path) {
+        Ok(recording) => recording,
+        Err(err) => {
+            eprintln!("Failed to read the recording {}", err);
+            return;
+        }
+    };
+    let events = limit_idle(events, options.idle_limit.or(header.idle_time_limit));
+
+    let window = ApplicationWindow::builder()
+        .application(app)
+        .title(APP_TITLE)
+        .build();
+
+    let terminal = AppTerm::make_terminal();
+    terminal.add_css_class("terminal");
+    config.apply(&terminal);
+    // the keys control the playback, there is nothing to type into
+    terminal.set_input_enabled(false);
+    window.set_child(Some(&terminal));
+
+    let player = Rc::new(Player {
+        name: name_of(&options.path),
+        terminal,
+        window: window.downgrade(),
+        size: (header.width, header.height),
+        duration: events.last().map(|(time, _)| *time).unwrap_or(0.0),
+        events,
+        speed: options.speed,
+        next: Cell::new(0),
+        position: Cell::new(0.0),
+        last_tick: Cell::new(Instant::now()),
+        paused: Cell::new(false),
+        shown_second: Cell::new(-1),
+    });
+    player.resize(header.width, header.height);
+    player.update_title(true);
+
+    let keys = EventControllerKey::new();
+    keys.set_propagation_phase(PropagationPhase::Capture);
+    let player_weak = Rc::downgrade(&player);
+    let window_weak = window.downgrade();
+    keys.connect_key_pressed(move |_, key, _, _| {
+        let Some(player) = player_weak.upgrade() else {
+            return glib::Propagation::Proceed;
+        };
+        match key {
+            gdk::Key::space => player.toggle_pause(),
+            gdk::Key::Left => player.seek(player.position.get() - SEEK_STEP),
+            gdk::Key::Right => player.seek(player.position.get() + SEEK_STEP),
+            gdk::Key::Home => player.seek(0.0),
+            gdk::Key::End => player.seek(player.duration),
+            gdk::Key::q => {
+                if let Some(window) = window_weak.upgrade() {
+                    window.close();
+                }
+            }
+            _ => return glib::Propagation::Proceed,
+        }
+        glib::Propagation::Stop
+    });
+    window.add_controller(keys);
+
+    // the timer owns the player and stops with the window
+    let window_weak = window.downgrade();
+    glib::timeout_add_local(TICK, move || {
+        if window_weak.upgrade().is_none() {
+            return glib::ControlFlow::Break;
+        }
+        player.tick();
+        glib::ControlFlow::Continue
+    });
+
+    window.present();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn times(events: &[(f64, Event)]) -> Vec<f64> {
+        events.iter().map(|(time, _)| *time).collect()
+    }
+
+    fn events(times: &[f64]) -> Vec<(f64, Event)> {
+        times.iter().map(|time| (*time, Event::Output(String::new()))).collect()
+    }
+
+    #[test]
+    fn idle_limit() {
+        let recorded = [0.5, 1.0, 11.0, 11.25, 20.0];
+        assert_eq!(times(&limit_idle(events(&recorded), None)), recorded);
+        assert_eq!(times(&limit_idle(events(&recorded), Some(2.0))), [0.5, 1.0, 3.0, 3.25, 5.25]);
+        // the pause before the first event is limited too
+        assert_eq!(times(&limit_idle(events(&[30.0, 31.0]), Some(1.5))), [1.5, 2.5]);
+        // time going back counts as no pause
+        assert_eq!(times(&limit_idle(events(&[2.0, 1.0, 3.0]), Some(5.0))), [2.0, 2.0, 4.0]);
+        assert!(limit_idle(Vec::new(), Some(1.0)).is_empty());
+    }
+}
@@ -0,0 +1,91 @@
+//
+// Named profiles
+//
+// A [Profile:<name>] section of termint.ini overrides the [Settings] and
+// [Colors] keys it contains (font, theme, colours, size...) and can set:
+//
+// shell = /usr/bin/zsh
+// working_directory = ~/src
+// command = ssh prod
+// env.EDITOR = vim          (one env.<NAME> key per variable)
+//
+// The profile comes from --profile NAME or the default_profile key of
+// [Settings]; the other command line options win over it. The registry
+// of the terminals keeps the profile each one was created with.
+//
+
+use std::{env, path::PathBuf};
+
+use ini::{Ini, Properties};
+
+const ENV_PREFIX: &str = "env.";
+
+pub fn section<'a>(ini: &'a Ini, name: &str) -> Option<&'a Properties> {
+    ini.section(Some(format!("Profile:{}", name)))
+}
+
+// The name of the profile to use: the one asked for on the command line,
+// or else the default one
+pub fn resolve(ini: &Ini, name: Option<&String>) -> Result<Option<String>, String> {
+    if let Some(name) = name {
+        return match section(ini, name) {
+            Some(_) => Ok(Some(name.clone())),
+            None => Err(format!("Unknown profile: {}", name)),
+        };
+    }
+
+    let default = ini.section(Some("Settings"))
+        .and_then(|settings| settings.get("default_profile"))
+        .map(str::trim)
+        .filter(|name| !name.is_empty());
+
+    match default {
+        Some(name) if section(ini, name).is_some() => Ok(Some(name.to_string())),
+        Some(name) => {
+            eprintln!("Warning: invalid value \"{}\" for \"default_profile\"", name);
+            Ok(None)
+        }
+        None => Ok(None),
+    }
+}
+
+// A copy of `base` with the keys of the profile on top
+pub fn overlay(base: Option<&Properties>, profile: &Properties) -> Properties {
+    let mut merged = base.cloned().unwrap_or_default();
+    for (key, value) in profile.iter() {
+        merged.insert(key, value);
+    }
+    merged
+}
+
+// The env.<NAME> keys of a profile
+pub fn env(profile: &Properties) -> Vec<(String, String)> {
+    profile.iter()
+        .filter_map(|(key, value)| {
+            key.strip_prefix(ENV_PREFIX)
+                .filter(|name| !name.is_empty())
+                .map(|name| (name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+// The working directory of a profile, with ~ standing for the home directory
+pub fn working_directory(profile: &Properties) -> Option<String> {
+    let dir = profile.get("working_directory")?.trim();
+    if dir.is_empty() {
+        return None;
+    }
+
+    let path = match dir.strip_prefix('~') {
+        Some(rest) => PathBuf::from(env::var("HOME").unwrap_or_default())
+            .join(rest.trim_start_matches('/')),
+        None => PathBuf::from(dir),
+    };
+
+    if path.is_dir() {
+        Some(path.to_string_lossy().to_string())
+    } else {
+        eprintln!("Warning: invalid value \"{}\" for \"working_directory\"", dir);
+        None
+    }
+}
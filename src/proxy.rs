@@ -0,0 +1,626 @@
+//
+// PTY proxy
+//
+// While something listens to the output of a terminal (a recording, or
+// confirm_paste, which needs to know about bracketed paste), the
+// terminal no longer reads the PTY of its child: everything the child
+// writes is read here, handed to the listeners and then written to a
+// second PTY, which is in raw mode so that VTE reads the bytes
+// unchanged. What VTE writes back (keys, pastes, answers to queries)
+// goes the other way, and the size of the terminal is copied to the PTY
+// of the child.
+//
+// When the last listener goes, the proxy stops reading the child, waits
+// for the terminal to read what was passed on and hands it back the PTY
+// of the child; a terminal nobody listens to costs no more than without
+// the proxy.
+//
+// Each direction stops reading while the other end is full, so a child
+// that prints faster than the terminal can draw is slowed down as it
+// would be without the proxy.
+//
+// The output is also scanned for what VTE does not report: bracketed
+// paste (CSI ? 2004 h and l).
+//
+
+use std::{
+    cell::{Cell, RefCell},
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, RawFd},
+        unix::fs::OpenOptionsExt,
+    },
+    rc::{Rc, Weak},
+    time::Duration,
+};
+
+use gtk4::{
+    gdk::FrameClock,
+    glib::{self, ControlFlow, IOCondition, SignalHandlerId, SourceId, WeakRef},
+    prelude::*,
+};
+
+use vte4::{
+    Pty,
+    PtyFlags,
+    Terminal,
+    TerminalExt,
+};
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+// Longest parameter string of a control sequence that is looked at
+const MAX_PARAMS: usize = 64;
+
+const BRACKETED_PASTE: &[u8] = b"2004";
+
+// How often a proxy that is being removed checks that the terminal has
+// read everything
+const DRAIN_INTERVAL: Duration = Duration::from_millis(10);
+
+// What a listener of a terminal is told
+pub enum Event<'a> {
+    // bytes written by the child
+    Output(&'a [u8]),
+    // the new size, in columns and rows
+    Resize(i32, i32),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct ListenerId(u32);
+
+type Listener = Rc<dyn Fn(&Event)>;
+
+#[derive(Default)]
+enum ScanState {
+    #[default]
+    Ground,
+    Escape,
+    Csi,
+    Osc,
+    // ESC in an OSC, which ends it if \ follows
+    OscEscape,
+}
+
+// Follows the control sequences of the output, across reads
+#[derive(Default)]
+struct Scanner {
+    state: ScanState,
+    params: Vec<u8>,
+}
+
+impl Scanner {
+
+    // Returns the bracketed paste mode set by a sequence ending with `byte`
+    fn feed(&mut self, byte: u8) -> Option<bool> {
+        match (&self.state, byte) {
+            (ScanState::Osc, 0x07) | (ScanState::OscEscape, b'\\') => self.state = ScanState::Ground,
+            (ScanState::Osc, 0x1b) => self.state = ScanState::OscEscape,
+            (ScanState::OscEscape, _) => {
+                self.state = ScanState::Escape;
+                return self.feed(byte);
+            }
+            (_, 0x1b) => self.state = ScanState::Escape,
+            // CAN and SUB cancel a sequence
+            (_, 0x18 | 0x1a) => self.state = ScanState::Ground,
+            (ScanState::Ground, _) => {}
+            (ScanState::Escape, b'[' | b']') => {
+                self.params.clear();
+                self.state = if byte == b'[' { ScanState::Csi } else { ScanState::Osc };
+            }
+            // RIS, a full reset
+            (ScanState::Escape, b'c') => {
+                self.state = ScanState::Ground;
+                return Some(false);
+            }
+            (ScanState::Escape, _) => self.state = ScanState::Ground,
+            (ScanState::Csi, 0x20..=0x3f) => {
+                if self.params.len() < MAX_PARAMS {
+                    self.params.push(byte);
+                }
+            }
+            (ScanState::Csi, 0x40..=0x7e) => {
+                self.state = ScanState::Ground;
+                return self.mode_change(byte);
+            }
+            // other controls are run in the middle of a sequence
+            (ScanState::Csi | ScanState::Osc, _) => {}
+        }
+        None
+    }
+
+    // DECSET and DECRST with 2004 among their parameters
+    fn mode_change(&self, last: u8) -> Option<bool> {
+        let params = self.params.strip_prefix(b"?")?;
+        if !params.split(|&byte| byte == b';').any(|param| param == BRACKETED_PASTE) {
+            return None;
+        }
+        match last {
+            b'h' => Some(true),
+            b'l' => Some(false),
+            _ => None,
+        }
+    }
+}
+
+// The output of a terminal, across the restarts of its child
+#[derive(Default)]
+pub struct Stream {
+    terminal: WeakRef<Terminal>,
+    // the PTY the child runs on
+    child_pty: RefCell<Option<Pty>>,
+    proxy: RefCell<Weak<Proxy>>,
+    listeners: RefCell<Vec<(ListenerId, Listener)>>,
+    last_id: Cell<u32>,
+    // keeps the proxy on to follow the modes without listeners
+    follow_modes: Cell<bool>,
+    scanner: RefCell<Scanner>,
+    bracketed_paste: Cell<bool>,
+}
+
+impl Stream {
+
+    // A stream for a terminal, which passes on its size when it changes
+    pub fn new(terminal: &Terminal) -> Rc<Self> {
+        let stream = Rc::new(Stream {
+            terminal: terminal.downgrade(),
+            ..Default::default()
+        });
+
+        // the terminal sets the size of its PTY when it is laid out
+        let layout: Rc<RefCell<Option<(FrameClock, SignalHandlerId)>>> = Rc::default();
+        let stream_weak = Rc::downgrade(&stream);
+        let handler = layout.clone();
+        terminal.connect_realize(move |term| {
+            let Some(clock) = term.frame_clock() else {
+                return;
+            };
+            let stream_weak = stream_weak.clone();
+            let id = clock.connect_layout(move |_| {
+                if let Some(stream) = stream_weak.upgrade() {
+                    stream.sync_size();
+                }
+            });
+            handler.replace(Some((clock, id)));
+        });
+        terminal.connect_unrealize(move |_| {
+            if let Some((clock, id)) = layout.take() {
+                clock.disconnect(id);
+            }
+        });
+
+        stream
+    }
+
+    // Gives the terminal `pty`, the one a new child is spawned on: through
+    // the proxy while the output is listened to, as it is otherwise
+    pub fn set_child(self: &Rc<Self>, pty: &Pty) {
+        let old = self.proxy.replace(Weak::new()).upgrade();
+        if let Some(old) = old {
+            old.stop();
+        }
+        self.child_pty.replace(Some(pty.clone()));
+        self.forget_modes();
+
+        let Some(terminal) = self.terminal.upgrade() else {
+            return;
+        };
+        if !self.in_use() || self.attach(&terminal, pty).is_err() {
+            terminal.set_pty(Some(pty));
+        }
+    }
+
+    pub fn connect(self: &Rc<Self>, listener: impl Fn(&Event) + 'static) -> ListenerId {
+        let id = ListenerId(self.last_id.get() + 1);
+        self.last_id.set(id.0);
+        self.listeners.borrow_mut().push((id, Rc::new(listener)));
+        self.update();
+        id
+    }
+
+    pub fn disconnect(self: &Rc<Self>, id: ListenerId) {
+        self.listeners.borrow_mut().retain(|(other, _)| *other != id);
+        self.update();
+    }
+
+    // Follows the modes of the output (bracketed paste) even when nobody
+    // listens to it, or stops
+    pub fn follow_modes(self: &Rc<Self>, follow: bool) {
+        if self.follow_modes.replace(follow) != follow {
+            self.update();
+        }
+    }
+
+    // Whether the program turned on bracketed paste, false when the
+    // modes are not followed
+    pub fn bracketed_paste(&self) -> bool {
+        self.bracketed_paste.get()
+    }
+
+    // Forgets the modes, when the terminal is reset
+    pub fn reset(&self) {
+        self.bracketed_paste.set(false);
+    }
+
+    // Forgets the modes and where the scanner was, when the output stops
+    // going through the proxy
+    fn forget_modes(&self) {
+        self.reset();
+        self.scanner.take();
+    }
+
+    fn in_use(&self) -> bool {
+        self.follow_modes.get() || !self.listeners.borrow().is_empty()
+    }
+
+    // Starts or removes the proxy of the running child, as needed
+    fn update(self: &Rc<Self>) {
+        let proxy = self.proxy.borrow().upgrade();
+        match proxy {
+            Some(proxy) if self.in_use() => proxy.resume(),
+            Some(proxy) => proxy.detach(),
+            None if self.in_use() => {
+                let child_pty = self.child_pty.borrow().clone();
+                if let (Some(terminal), Some(child_pty)) = (self.terminal.upgrade(), child_pty) {
+                    // the terminal keeps reading the child if it fails
+                    let _ = self.attach(&terminal, &child_pty);
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn attach(self: &Rc<Self>, terminal: &Terminal, child_pty: &Pty) -> io::Result<()> {
+        attach(terminal, child_pty, self)
+            .inspect_err(|err| eprintln!("Failed to set up the PTY proxy: {}", err))
+    }
+
+    // Follows the modes set by the output
+    fn scan(&self, data: &[u8]) {
+        let mut scanner = self.scanner.borrow_mut();
+        for &byte in data {
+            if let Some(on) = scanner.feed(byte) {
+                self.bracketed_paste.set(on);
+            }
+        }
+    }
+
+    fn emit(&self, event: &Event) {
+        // a listener may connect or disconnect others
+        let listeners: Vec<Listener> = self.listeners.borrow()
+            .iter()
+            .map(|(_, listener)| listener.clone())
+            .collect();
+        for listener in listeners {
+            listener(event);
+        }
+    }
+
+    fn sync_size(&self) {
+        let proxy = self.proxy.borrow().upgrade();
+        if let Some(proxy) = proxy {
+            proxy.sync_size(self);
+        }
+    }
+}
+
+// One direction of the proxy: everything read from `from` is written
+// to `to`
+struct Pipe {
+    from: File,
+    to: File,
+    // read but not written yet, the reading waits for it
+    pending: RefCell<Vec<u8>>,
+    read_source: RefCell<Option<SourceId>>,
+    write_source: RefCell<Option<SourceId>>,
+}
+
+impl Pipe {
+
+    fn new(from: File, to: File) -> Self {
+        Pipe {
+            from,
+            to,
+            pending: RefCell::new(Vec::new()),
+            read_source: RefCell::new(None),
+            write_source: RefCell::new(None),
+        }
+    }
+
+    // Writes what the other end takes now, returns false if some is left
+    fn write(&self, data: &[u8]) -> io::Result<bool> {
+        let mut pending = self.pending.borrow_mut();
+        pending.extend_from_slice(data);
+        while !pending.is_empty() {
+            match (&self.to).write(&pending) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    pending.drain(..n);
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(true)
+    }
+
+    fn remove_sources(&self) {
+        for source in [&self.read_source, &self.write_source] {
+            if let Some(id) = source.take() {
+                id.remove();
+            }
+        }
+    }
+}
+
+// The two PTYs of one run of the child
+struct Proxy {
+    child_pty: Pty,
+    terminal_pty: WeakRef<Pty>,
+    output: Pipe,
+    input: Pipe,
+    size: Cell<(i32, i32)>,
+    stream: Weak<Stream>,
+    // the child is no longer read, the terminal gets its PTY back once
+    // it has read the rest
+    detaching: Cell<bool>,
+}
+
+impl Proxy {
+
+    fn pipe(&self, output: bool) -> &Pipe {
+        if output {
+            &self.output
+        } else {
+            &self.input
+        }
+    }
+
+    fn watch_read(self: &Rc<Self>, output: bool) {
+        let proxy = self.clone();
+        let id = glib::unix_fd_add_local(
+            self.pipe(output).from.as_raw_fd(),
+            IOCondition::IN | IOCondition::HUP | IOCondition::ERR,
+            move |_, _| proxy.readable(output),
+        );
+        self.pipe(output).read_source.replace(Some(id));
+    }
+
+    fn watch_write(self: &Rc<Self>, output: bool) {
+        let proxy = self.clone();
+        let id = glib::unix_fd_add_local(
+            self.pipe(output).to.as_raw_fd(),
+            IOCondition::OUT | IOCondition::HUP | IOCondition::ERR,
+            move |_, _| proxy.writable(output),
+        );
+        self.pipe(output).write_source.replace(Some(id));
+    }
+
+    fn readable(self: &Rc<Self>, output: bool) -> ControlFlow {
+        let pipe = self.pipe(output);
+        let mut buffer = vec![0; BUFFER_SIZE];
+        let data = match (&pipe.from).read(&mut buffer) {
+            // the child and everything it started are gone (EIO on Linux),
+            // or the terminal was closed
+            Ok(0) => return self.close(&pipe.read_source),
+            Ok(n) => &buffer[..n],
+            Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted) => {
+                return ControlFlow::Continue;
+            }
+            Err(_) => return self.close(&pipe.read_source),
+        };
+
+        if let Some(stream) = self.stream.upgrade().filter(|_| output) {
+            stream.scan(data);
+            stream.emit(&Event::Output(data));
+        }
+
+        match pipe.write(data) {
+            Ok(true) => ControlFlow::Continue,
+            // wait for the other end before reading more
+            Ok(false) => {
+                pipe.read_source.take();
+                self.watch_write(output);
+                ControlFlow::Break
+            }
+            Err(_) => self.close(&pipe.read_source),
+        }
+    }
+
+    fn writable(self: &Rc<Self>, output: bool) -> ControlFlow {
+        let pipe = self.pipe(output);
+        match pipe.write(&[]) {
+            Ok(true) => {
+                pipe.write_source.take();
+                if !(output && self.detaching.get()) {
+                    self.watch_read(output);
+                }
+                ControlFlow::Break
+            }
+            Ok(false) => ControlFlow::Continue,
+            Err(_) => self.close(&pipe.write_source),
+        }
+    }
+
+    // Stops both directions from the callback of `current`, which
+    // removes itself by returning Break. Dropping the proxy closes the
+    // PTY of the child, which hangs up what is left of it, and the end
+    // of the PTY of the terminal, which makes it see the end of the output.
+    fn close(&self, current: &RefCell<Option<SourceId>>) -> ControlFlow {
+        current.take();
+        self.stop();
+        // this run of the child is over
+        if let Some(stream) = self.stream.upgrade() {
+            let current = stream.child_pty.borrow().as_ref() == Some(&self.child_pty);
+            if current {
+                stream.child_pty.replace(None);
+            }
+        }
+        ControlFlow::Break
+    }
+
+    fn stop(&self) {
+        self.detaching.set(false);
+        self.output.remove_sources();
+        self.input.remove_sources();
+    }
+
+    // Stops reading the child and, once the terminal has read what was
+    // passed on, gives it back the PTY of the child
+    fn detach(self: &Rc<Self>) {
+        if self.detaching.replace(true) {
+            return;
+        }
+        if let Some(id) = self.output.read_source.take() {
+            id.remove();
+        }
+
+        let proxy = self.clone();
+        glib::timeout_add_local(DRAIN_INTERVAL, move || {
+            // resumed or stopped
+            if !proxy.detaching.get() {
+                return ControlFlow::Break;
+            }
+            let terminal_pty = proxy.terminal_pty.upgrade();
+            let drained = proxy.output.pending.borrow().is_empty()
+                && terminal_pty.is_none_or(|pty| unread(pty.fd().as_raw_fd()) == 0);
+            if !drained {
+                return ControlFlow::Continue;
+            }
+            proxy.hand_back();
+            ControlFlow::Break
+        });
+    }
+
+    // Reads the child again, when a listener comes back while detaching
+    fn resume(self: &Rc<Self>) {
+        if !self.detaching.replace(false) {
+            return;
+        }
+        // a pending write reads again when it is done
+        if self.output.read_source.borrow().is_none() && self.output.write_source.borrow().is_none() {
+            self.watch_read(true);
+        }
+    }
+
+    fn hand_back(&self) {
+        // what VTE wrote last still goes to the child
+        let mut buffer = vec![0; BUFFER_SIZE];
+        while let Ok(n @ 1..) = (&self.input.from).read(&mut buffer) {
+            if !matches!(self.input.write(&buffer[..n]), Ok(true)) {
+                break;
+            }
+        }
+        self.stop();
+
+        let Some(stream) = self.stream.upgrade() else {
+            return;
+        };
+        stream.proxy.replace(Weak::new());
+        stream.forget_modes();
+        if let Some(terminal) = stream.terminal.upgrade() {
+            terminal.set_pty(Some(&self.child_pty));
+        }
+    }
+
+    fn sync_size(&self, stream: &Stream) {
+        let Some((rows, columns)) = self.terminal_pty.upgrade().and_then(|pty| pty.size().ok()) else {
+            return;
+        };
+        if self.size.replace((rows, columns)) == (rows, columns) {
+            return;
+        }
+        if let Err(err) = self.child_pty.set_size(rows, columns) {
+            eprintln!("Failed to resize the PTY: {}", err);
+        }
+        stream.emit(&Event::Resize(columns, rows));
+    }
+}
+
+// How many bytes written to a PTY its master has not read yet
+fn unread(master: RawFd) -> libc::c_int {
+    let mut count: libc::c_int = 0;
+    unsafe {
+        if libc::ioctl(master, libc::FIONREAD, &mut count) != 0 {
+            return 0;
+        }
+    }
+    count
+}
+
+fn set_nonblocking(fd: BorrowedFd) -> io::Result<()> {
+    let fd = fd.as_raw_fd();
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+// Opens the other end of the PTY of the terminal, in raw mode
+fn open_terminal_side(master: RawFd) -> io::Result<File> {
+    let mut name = [0 as libc::c_char; 128];
+    let path = unsafe {
+        if libc::unlockpt(master) != 0 || libc::ptsname_r(master, name.as_mut_ptr(), name.len()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        std::ffi::CStr::from_ptr(name.as_ptr()).to_string_lossy().to_string()
+    };
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK | libc::O_CLOEXEC)
+        .open(path)?;
+
+    unsafe {
+        let mut termios = std::mem::zeroed::<libc::termios>();
+        if libc::tcgetattr(file.as_raw_fd(), &mut termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        libc::cfmakeraw(&mut termios);
+        if libc::tcsetattr(file.as_raw_fd(), libc::TCSANOW, &termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(file)
+}
+
+// Gives the terminal a PTY of its own and connects it to `child_pty`,
+// the one the child runs on
+fn attach(terminal: &Terminal, child_pty: &Pty, stream: &Rc<Stream>) -> io::Result<()> {
+    let terminal_pty = Pty::new_sync(PtyFlags::DEFAULT, None::<&gtk4::gio::Cancellable>)
+        .map_err(|err| io::Error::other(err.to_string()))?;
+    let terminal_side = open_terminal_side(terminal_pty.fd().as_raw_fd())?;
+
+    let child_side = File::from(child_pty.fd().try_clone_to_owned()?);
+    set_nonblocking(child_side.as_fd())?;
+    // VTE sets this on its own PTY only
+    if let Err(err) = child_pty.set_utf8(true) {
+        eprintln!("Failed to set the PTY to UTF-8: {}", err);
+    }
+
+    let proxy = Rc::new(Proxy {
+        child_pty: child_pty.clone(),
+        terminal_pty: terminal_pty.downgrade(),
+        output: Pipe::new(child_side.try_clone()?, terminal_side.try_clone()?),
+        input: Pipe::new(terminal_side, child_side),
+        size: Cell::new((0, 0)),
+        stream: Rc::downgrade(stream),
+        detaching: Cell::new(false),
+    });
+
+    terminal.set_pty(Some(&terminal_pty));
+    stream.proxy.replace(Rc::downgrade(&proxy));
+    proxy.sync_size(stream);
+
+    // the watches keep the proxy alive until it closes
+    proxy.watch_read(true);
+    proxy.watch_read(false);
+    Ok(())
+}
@@ -0,0 +1,224 @@
+//
+// Session recording
+//
+// `termint --record FILE.cast` records the first terminal of the window
+// and the win.toggle-recording action starts or stops recording the
+// focused one, into recordings_directory of [Settings] (the home
+// directory if it is empty) as termint-<date>-<time>.cast.
+//
+// Recordings use the asciicast v2 format of asciinema: a JSON header line
+// and then one [time, "o", data] line per output of the program, as read
+// from its PTY, and one [time, "r", "COLSxROWS"] line per resize.
+//
+
+use std::{
+    cell::{Cell, RefCell},
+    env,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    rc::{Rc, Weak},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use gtk4::{
+    glib::{self, WeakRef},
+    prelude::*,
+};
+
+use ini::Ini;
+
+use vte4::{
+    Terminal,
+    TerminalExt,
+};
+
+use crate::{
+    asciicast,
+    proxy::{Event, ListenerId, Stream},
+};
+
+struct Recording {
+    path: PathBuf,
+    file: RefCell<BufWriter<File>>,
+    start: Instant,
+    // the end of a UTF-8 sequence cut in two by a read
+    carry: RefCell<Vec<u8>>,
+    stream: Weak<Stream>,
+    listener: Cell<Option<ListenerId>>,
+}
+
+// The terminals being recorded
+#[derive(Default)]
+pub struct Recordings {
+    active: RefCell<Vec<(WeakRef<Terminal>, Weak<Recording>)>>,
+}
+
+// Decodes the complete UTF-8 sequences of `bytes`, keeps the rest for
+// the next read
+fn decode(bytes: &mut Vec<u8>) -> String {
+    let mut text = String::new();
+    let mut rest = &bytes[..];
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(valid) => {
+                text.push_str(valid);
+                rest = &[];
+                break;
+            }
+            Err(err) => {
+                let (valid, after) = rest.split_at(err.valid_up_to());
+                text.push_str(&String::from_utf8_lossy(valid));
+                match err.error_len() {
+                    Some(len) => {
+                        text.push(char::REPLACEMENT_CHARACTER);
+                        rest = &after[len..];
+                    }
+                    None => {
+                        rest = after;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    *bytes = rest.to_vec();
+    text
+}
+
+impl Recording {
+
+    fn create(terminal: &Terminal, stream: &Rc<Stream>, path: &Path) -> std::io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or(0);
+        writeln!(
+            file,
+            r#"{{"version": 2, "width": {}, "height": {}, "timestamp": {}, "env": {{"SHELL": {}, "TERM": "xterm-256color"}}}}"#,
+            terminal.column_count(),
+            terminal.row_count(),
+            timestamp,
+            asciicast::string(&env::var("SHELL").unwrap_or_default()),
+        )?;
+        file.flush()?;
+
+        Ok(Recording {
+            path: path.to_path_buf(),
+            file: RefCell::new(file),
+            start: Instant::now(),
+            carry: RefCell::new(Vec::new()),
+            stream: Rc::downgrade(stream),
+            listener: Cell::new(None),
+        })
+    }
+
+    fn event(&self, code: &str, data: &str) {
+        let mut file = self.file.borrow_mut();
+        let time = self.start.elapsed().as_secs_f64();
+        // flushed every time, the recording may never be stopped
+        let result = writeln!(file, "[{:.6}, \"{}\", {}]", time, code, asciicast::string(data))
+            .and_then(|_| file.flush());
+        if let Err(err) = result {
+            eprintln!("Failed to write the recording {}: {}", self.path.display(), err);
+        }
+    }
+
+    fn record(&self, event: &Event) {
+        match event {
+            Event::Output(bytes) => {
+                let text = {
+                    let mut carry = self.carry.borrow_mut();
+                    carry.extend_from_slice(bytes);
+                    decode(&mut carry)
+                };
+                if !text.is_empty() {
+                    self.event("o", &text);
+                }
+            }
+            Event::Resize(columns, rows) => self.event("r", &format!("{}x{}", columns, rows)),
+        }
+    }
+}
+
+impl Recordings {
+
+    fn find(&self, terminal: &Terminal) -> Option<Rc<Recording>> {
+        let mut active = self.active.borrow_mut();
+        // forget the recordings of closed terminals
+        active.retain(|(term, recording)| term.upgrade().is_some() && recording.strong_count() > 0);
+        active.iter()
+            .find(|(term, _)| term.upgrade().as_ref() == Some(terminal))
+            .and_then(|(_, recording)| recording.upgrade())
+    }
+
+    pub fn is_recording(&self, terminal: &Terminal) -> bool {
+        self.find(terminal).is_some()
+    }
+
+    // Starts recording the output of a terminal into `path`
+    pub fn start(&self, terminal: &Terminal, stream: &Rc<Stream>, path: &Path) -> Result<(), String> {
+        if self.find(terminal).is_some() {
+            return Err("the terminal is already being recorded".to_string());
+        }
+        let recording = Recording::create(terminal, stream, path)
+            .map(Rc::new)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+
+        // the stream keeps the recording alive until it is stopped
+        let rec = recording.clone();
+        let listener = stream.connect(move |event| rec.record(event));
+        recording.listener.set(Some(listener));
+
+        self.active.borrow_mut().push((terminal.downgrade(), Rc::downgrade(&recording)));
+        Ok(())
+    }
+
+    // Stops recording a terminal, returns false if it was not recorded
+    pub fn stop(&self, terminal: &Terminal) -> bool {
+        let Some(recording) = self.find(terminal) else {
+            return false;
+        };
+        if let (Some(stream), Some(listener)) = (recording.stream.upgrade(), recording.listener.take()) {
+            stream.disconnect(listener);
+        }
+        true
+    }
+
+    // Starts recording a terminal into a new file, or stops recording it
+    pub fn toggle(&self, terminal: &Terminal, stream: &Rc<Stream>, ini: &Ini) {
+        if self.stop(terminal) {
+            return;
+        }
+        let path = recordings_directory(ini).join(format!(
+            "termint-{}.cast",
+            glib::DateTime::now_local()
+                .and_then(|now| now.format("%Y%m%d-%H%M%S"))
+                .map(|name| name.to_string())
+                .unwrap_or_else(|_| "recording".to_string()),
+        ));
+        if let Err(err) = self.start(terminal, stream, &path) {
+            eprintln!("Failed to start recording: {}", err);
+        }
+    }
+}
+
+fn recordings_directory(ini: &Ini) -> PathBuf {
+    let home = PathBuf::from(env::var("HOME").unwrap_or_default());
+    let dir = ini.section(Some("Settings"))
+        .and_then(|settings| settings.get("recordings_directory"))
+        .map(str::trim)
+        .filter(|dir| !dir.is_empty());
+    match dir {
+        Some(dir) => match dir.strip_prefix("~/") {
+            Some(rest) => home.join(rest),
+            None => PathBuf::from(dir),
+        },
+        None => home,
+    }
+}
+
+pub fn default_settings(settings: &mut ini::SectionSetter) {
+    settings.set("recordings_directory", "");
+}
@@ -0,0 +1,344 @@
+//
+// Remote control over D-Bus
+//
+// With --app-id the application is unique on the session bus and exports
+// the org.gtk_rs.Termint.Remote interface on its object path, e.g.
+//
+// gdbus call --session --dest org.gtk_rs.work --object-path /org/gtk_rs/work \
+//     --method org.gtk_rs.Termint.Remote.SendText 1 "ls\n"
+//
+// Terminals are addressed by an ID that the shell also finds in the
+// TERMINT_TERMINAL_ID environment variable. Windows use the GTK window
+// ID, 0 meaning the active window. SetTitle fixes the title of a
+// terminal until it is called again with an empty title.
+//
+
+use std::{
+    cell::{Cell, RefCell},
+    os::raw::c_long,
+    path::Path,
+    rc::Rc,
+};
+
+use gtk4::{
+    gio::{DBusConnection, DBusMethodInvocation, DBusNodeInfo},
+    glib::{self, Variant, WeakRef},
+    prelude::*,
+    Application,
+    ApplicationWindow,
+};
+
+use vte4::{
+    Format,
+    Terminal,
+    TerminalExt,
+};
+
+use crate::{
+    panes,
+    proxy,
+    AppTerm,
+    Launch,
+};
+
+pub const TERMINAL_ID_VAR: &str = "TERMINT_TERMINAL_ID";
+
+const INTERFACE: &str = "org.gtk_rs.Termint.Remote";
+
+const INTROSPECTION: &str = r#"
+<node>
+  <interface name="org.gtk_rs.Termint.Remote">
+    <method name="ListWindows">
+      <arg type="a(us)" name="windows" direction="out"/>
+    </method>
+    <method name="ListTerminals">
+      <arg type="a(uuss)" name="terminals" direction="out"/>
+    </method>
+    <method name="OpenWindow">
+      <arg type="s" name="command" direction="in"/>
+      <arg type="s" name="working_directory" direction="in"/>
+      <arg type="u" name="terminal" direction="out"/>
+    </method>
+    <method name="OpenTab">
+      <arg type="u" name="window" direction="in"/>
+      <arg type="s" name="command" direction="in"/>
+      <arg type="s" name="working_directory" direction="in"/>
+      <arg type="u" name="terminal" direction="out"/>
+    </method>
+    <method name="SendText">
+      <arg type="u" name="terminal" direction="in"/>
+      <arg type="s" name="text" direction="in"/>
+    </method>
+    <method name="GetText">
+      <arg type="u" name="terminal" direction="in"/>
+      <arg type="s" name="text" direction="out"/>
+    </method>
+    <method name="SetTitle">
+      <arg type="u" name="terminal" direction="in"/>
+      <arg type="s" name="title" direction="in"/>
+    </method>
+    <method name="CloseTerminal">
+      <arg type="u" name="terminal" direction="in"/>
+    </method>
+  </interface>
+</node>
+"#;
+
+const ERROR_NO_WINDOW: &str = "org.gtk_rs.Termint.Error.NoSuchWindow";
+const ERROR_NO_TERMINAL: &str = "org.gtk_rs.Termint.Error.NoSuchTerminal";
+const ERROR_INVALID_ARGS: &str = "org.freedesktop.DBus.Error.InvalidArgs";
+
+// D-Bus error name and message
+type Error = (&'static str, String);
+
+struct Entry {
+    id: u32,
+    terminal: WeakRef<Terminal>,
+    // what the terminal was started with
+    command: String,
+    working_dir: String,
+    profile: Option<String>,
+    // set with SetTitle, shown instead of the title of the program
+    title: Option<String>,
+    // what the child writes, across restarts
+    stream: Rc<proxy::Stream>,
+}
+
+// Gives every terminal an ID and finds it again
+#[derive(Default)]
+pub struct Registry {
+    last_id: Cell<u32>,
+    terminals: RefCell<Vec<Entry>>,
+}
+
+impl Registry {
+
+    pub fn register(&self, terminal: &Terminal, command: &str, working_dir: &str, profile: Option<&str>) -> u32 {
+        let id = self.last_id.get() + 1;
+        self.last_id.set(id);
+        let mut terminals = self.terminals.borrow_mut();
+        terminals.retain(|entry| entry.terminal.upgrade().is_some());
+        terminals.push(Entry {
+            id,
+            terminal: terminal.downgrade(),
+            command: command.to_string(),
+            working_dir: working_dir.to_string(),
+            profile: profile.map(str::to_string),
+            title: None,
+            stream: proxy::Stream::new(terminal),
+        });
+        id
+    }
+
+    pub fn get(&self, id: u32) -> Option<Terminal> {
+        self.terminals.borrow()
+            .iter()
+            .find(|entry| entry.id == id)
+            .and_then(|entry| entry.terminal.upgrade())
+    }
+
+    pub fn id_of(&self, terminal: &Terminal) -> Option<u32> {
+        self.find(terminal, |entry| entry.id)
+    }
+
+    // The command and the working directory a terminal was started with
+    pub fn started_with(&self, terminal: &Terminal) -> Option<(String, String)> {
+        self.find(terminal, |entry| (entry.command.clone(), entry.working_dir.clone()))
+    }
+
+    // The profile a terminal was created with
+    pub fn profile_of(&self, terminal: &Terminal) -> Option<String> {
+        self.find(terminal, |entry| entry.profile.clone()).flatten()
+    }
+
+    pub fn title_of(&self, terminal: &Terminal) -> Option<String> {
+        self.find(terminal, |entry| entry.title.clone()).flatten()
+    }
+
+    pub fn stream_of(&self, terminal: &Terminal) -> Option<Rc<proxy::Stream>> {
+        self.find(terminal, |entry| entry.stream.clone())
+    }
+
+    // Sets or (with None) clears the title that overrides the program's
+    fn set_title(&self, id: u32, title: Option<String>) {
+        if let Some(entry) = self.terminals.borrow_mut().iter_mut().find(|entry| entry.id == id) {
+            entry.title = title;
+        }
+    }
+
+    fn find<T>(&self, terminal: &Terminal, f: impl Fn(&Entry) -> T) -> Option<T> {
+        self.terminals.borrow()
+            .iter()
+            .find(|entry| entry.terminal.upgrade().as_ref() == Some(terminal))
+            .map(f)
+    }
+}
+
+// Exports the interface on the connection of a unique application.
+// Every instance has an application ID (for its notifications) and the
+// separate ones share it, so only an --app-id is remote controlled.
+pub fn export(app: &Application, app_term: &Rc<AppTerm>) {
+    if app_term.app_id.is_empty() {
+        return;
+    }
+    let (Some(connection), Some(object_path)) = (app.dbus_connection(), app.dbus_object_path()) else {
+        return;
+    };
+
+    let interface = DBusNodeInfo::for_xml(INTROSPECTION)
+        .ok()
+        .and_then(|node| node.lookup_interface(INTERFACE))
+        .expect("invalid D-Bus introspection data");
+
+    let app_weak = app.downgrade();
+    let app_term = app_term.clone();
+    let registration = connection.register_object(
+        &object_path,
+        &interface,
+        move |_: DBusConnection, _: &str, _: &str, _: &str, method: &str, params: Variant, invocation: DBusMethodInvocation| {
+            let Some(app) = app_weak.upgrade() else {
+                return;
+            };
+            match call(&app, &app_term, method, &params) {
+                Ok(value) => invocation.return_value(value.as_ref()),
+                Err((name, message)) => invocation.return_dbus_error(name, &message),
+            }
+        },
+        |_, _, _, _, _| unreachable!("the interface has no properties"),
+        |_, _, _, _, _, _| false,
+    );
+
+    if let Err(err) = registration {
+        eprintln!("Warning: unable to export the remote control interface: {}", err);
+    }
+}
+
+fn call(app: &Application, app_term: &AppTerm, method: &str, params: &Variant) -> Result<Option<Variant>, Error> {
+    match method {
+        "ListWindows" => {
+            let windows: Vec<(u32, String)> = app.windows()
+                .iter()
+                .filter_map(|window| window.downcast_ref::<ApplicationWindow>())
+                .map(|window| (window.id(), window.title().map(String::from).unwrap_or_default()))
+                .collect();
+            Ok(Some((windows,).to_variant()))
+        }
+        "ListTerminals" => {
+            let mut terminals: Vec<(u32, u32, String, String)> = Vec::new();
+            for window in app.windows() {
+                let Some(window) = window.downcast_ref::<ApplicationWindow>() else {
+                    continue;
+                };
+                for terminal in panes::terminals(window.upcast_ref()) {
+                    if let Some(id) = app_term.terminals.id_of(&terminal) {
+                        terminals.push((
+                            id,
+                            window.id(),
+                            terminal.window_title().map(String::from).unwrap_or_default(),
+                            terminal.current_directory_uri().map(String::from).unwrap_or_default(),
+                        ));
+                    }
+                }
+            }
+            Ok(Some((terminals,).to_variant()))
+        }
+        "OpenWindow" => {
+            let (command, working_dir) = args::<(String, String)>(params)?;
+            let launch = Launch {
+                command,
+                working_dir: checked_dir(working_dir)?,
+                dropdown: false,
+                toggle: false,
+                record: None,
+                play: None,
+                dump_on_exit: None,
+                ..app_term.launch.clone()
+            };
+            let terminal = app_term.open_window(app, &launch)
+                .and_then(|window| panes::terminals(window.upcast_ref()).into_iter().next())
+                .ok_or((ERROR_NO_WINDOW, "the window could not be opened".to_string()))?;
+            Ok(Some((app_term.terminals.id_of(&terminal).unwrap_or_default(),).to_variant()))
+        }
+        "OpenTab" => {
+            let (window_id, command, working_dir) = args::<(u32, String, String)>(params)?;
+            let window = if window_id == 0 {
+                app.active_window()
+            } else {
+                app.window_by_id(window_id)
+            };
+            let notebook = window
+                .and_downcast::<ApplicationWindow>()
+                .and_then(|window| AppTerm::notebook_of(&window))
+                .ok_or((ERROR_NO_WINDOW, format!("no window {}", window_id)))?;
+            let launch = Launch {
+                working_dir: checked_dir(working_dir)?,
+                record: None,
+                play: None,
+                dump_on_exit: None,
+                ..app_term.launch.clone()
+            };
+            let options = app_term.term_options(&launch);
+            let terminal = AppTerm::add_tab(&notebook, &options, &command);
+            Ok(Some((app_term.terminals.id_of(&terminal).unwrap_or_default(),).to_variant()))
+        }
+        "SendText" => {
+            let (id, text) = args::<(u32, String)>(params)?;
+            terminal(app_term, id)?.feed_child(text.as_bytes());
+            Ok(None)
+        }
+        "GetText" => {
+            let (id,) = args::<(u32,)>(params)?;
+            Ok(Some((visible_text(&terminal(app_term, id)?),).to_variant()))
+        }
+        "SetTitle" => {
+            let (id, title) = args::<(u32, String)>(params)?;
+            let terminal = terminal(app_term, id)?;
+            // an empty title hands the title back to the program
+            app_term.terminals.set_title(id, Some(title).filter(|title| !title.is_empty()));
+            AppTerm::update_titles(&terminal, app_term.config(), &app_term.terminals, &app_term.recordings);
+            Ok(None)
+        }
+        "CloseTerminal" => {
+            let (id,) = args::<(u32,)>(params)?;
+            AppTerm::close_terminal(&terminal(app_term, id)?);
+            Ok(None)
+        }
+        _ => Err(("org.freedesktop.DBus.Error.UnknownMethod", format!("unknown method {}", method))),
+    }
+}
+
+fn args<T: glib::variant::FromVariant>(params: &Variant) -> Result<T, Error> {
+    params.get::<T>()
+        .ok_or((ERROR_INVALID_ARGS, format!("unexpected arguments {}", params.type_())))
+}
+
+fn terminal(app_term: &AppTerm, id: u32) -> Result<Terminal, Error> {
+    app_term.terminals.get(id)
+        .ok_or((ERROR_NO_TERMINAL, format!("no terminal {}", id)))
+}
+
+// An empty directory keeps the default one
+fn checked_dir(dir: String) -> Result<String, Error> {
+    if dir.is_empty() || Path::new(&dir).is_dir() {
+        Ok(dir)
+    } else {
+        Err((ERROR_INVALID_ARGS, format!("the directory does not exist: {}", dir)))
+    }
+}
+
+// The rows currently on screen
+fn visible_text(terminal: &Terminal) -> String {
+    let mut top = terminal.vadjustment().map(|adj| adj.value()).unwrap_or(0.0);
+    if terminal.is_scroll_unit_is_pixels() {
+        top /= terminal.char_height().max(1) as f64;
+    }
+    let top = top as c_long;
+    let (text, _) = terminal.text_range_format(
+        Format::Text,
+        top,
+        0,
+        top + terminal.row_count() - 1,
+        terminal.column_count(),
+    );
+    text.map(String::from).unwrap_or_default()
+}
@@ -0,0 +1,125 @@
+//
+// Scrollback and scrolling behaviour
+//
+// Read from the [Settings] section of termint.ini:
+//
+// scrollback_lines = 10000     (a number, 0 for none or "unlimited")
+// scroll_on_output = false     (jump to the bottom on new output)
+// scroll_on_keystroke = true   (jump to the bottom when typing)
+// scroll_unit_is_pixels = false (smooth scrolling instead of whole lines)
+// scrollbar = overlay          (always, overlay or never)
+// scrollbar_position = right   (right or left)
+//
+
+use std::os::raw::c_long;
+
+use gtk4::{
+    CornerType,
+    PolicyType,
+    ScrolledWindow,
+};
+
+use ini::Properties;
+
+use vte4::{
+    Terminal,
+    TerminalExt,
+};
+
+const DEFAULT_SCROLLBACK_LINES: c_long = 10000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scrollbar {
+    Always,
+    Overlay,
+    Never,
+}
+
+#[derive(Debug, Clone)]
+pub struct Scrolling {
+    scrollback_lines: c_long,
+    scroll_on_output: bool,
+    scroll_on_keystroke: bool,
+    scroll_unit_is_pixels: bool,
+    scrollbar: Scrollbar,
+    scrollbar_on_left: bool,
+}
+
+impl Scrolling {
+
+    pub fn default_settings(settings: &mut ini::SectionSetter) {
+        settings
+            .set("scrollback_lines", DEFAULT_SCROLLBACK_LINES.to_string())
+            .set("scroll_on_output", "false")
+            .set("scroll_on_keystroke", "true")
+            .set("scroll_unit_is_pixels", "false")
+            .set("scrollbar", "overlay")
+            .set("scrollbar_position", "right");
+    }
+
+    pub fn from_settings(settings: &Properties) -> Self {
+        let scrollback_lines = match settings.get("scrollback_lines").map(str::trim) {
+            // VTE treats a negative size as unlimited
+            Some(value) if value.eq_ignore_ascii_case("unlimited") => -1,
+            Some(value) => value.parse::<c_long>()
+                .map(|lines| lines.max(-1))
+                .unwrap_or_else(|_| {
+                    eprintln!("Warning: invalid value \"{}\" for \"scrollback_lines\"", value);
+                    DEFAULT_SCROLLBACK_LINES
+                }),
+            None => DEFAULT_SCROLLBACK_LINES,
+        };
+
+        let scrollbar = match settings.get("scrollbar").map(str::trim) {
+            Some("always") => Scrollbar::Always,
+            Some("never") => Scrollbar::Never,
+            Some("overlay") | None => Scrollbar::Overlay,
+            Some(value) => {
+                eprintln!("Warning: invalid value \"{}\" for \"scrollbar\"", value);
+                Scrollbar::Overlay
+            }
+        };
+
+        let scrollbar_on_left = match settings.get("scrollbar_position").map(str::trim) {
+            Some("left") => true,
+            Some("right") | None => false,
+            Some(value) => {
+                eprintln!("Warning: invalid value \"{}\" for \"scrollbar_position\"", value);
+                false
+            }
+        };
+
+        Scrolling {
+            scrollback_lines,
+            scroll_on_output: crate::get_bool(settings, "scroll_on_output", false),
+            scroll_on_keystroke: crate::get_bool(settings, "scroll_on_keystroke", true),
+            scroll_unit_is_pixels: crate::get_bool(settings, "scroll_unit_is_pixels", false),
+            scrollbar,
+            scrollbar_on_left,
+        }
+    }
+
+    pub fn apply(&self, terminal: &Terminal) {
+        terminal.set_scrollback_lines(self.scrollback_lines);
+        terminal.set_scroll_on_output(self.scroll_on_output);
+        terminal.set_scroll_on_keystroke(self.scroll_on_keystroke);
+        terminal.set_scroll_unit_is_pixels(self.scroll_unit_is_pixels);
+    }
+
+    pub fn apply_to_pane(&self, scrolled_window: &ScrolledWindow) {
+        let policy = match self.scrollbar {
+            Scrollbar::Always => PolicyType::Always,
+            Scrollbar::Overlay => PolicyType::Automatic,
+            // hidden, but the terminal can still be scrolled
+            Scrollbar::Never => PolicyType::External,
+        };
+        scrolled_window.set_policy(PolicyType::Never, policy);
+        scrolled_window.set_overlay_scrolling(self.scrollbar == Scrollbar::Overlay);
+        // the placement is where the content goes, relative to the scrollbar
+        scrolled_window.set_placement(if self.scrollbar_on_left {
+            CornerType::TopRight
+        } else {
+            CornerType::TopLeft
+        });
+    }
+}
@@ -0,0 +1,250 @@
+//
+// Search in the scrollback
+//
+// A search bar at the top of the window, wired to the VTE search of the
+// terminal that was active when it was opened. The pattern is plain
+// text unless the regex toggle is on.
+//
+
+use std::{
+    cell::RefCell,
+    rc::Rc,
+};
+
+use gtk4::{
+    glib::{self, WeakRef},
+    prelude::*,
+    Button,
+    Label,
+    Orientation,
+    SearchBar,
+    SearchEntry,
+    ToggleButton,
+};
+
+use vte4::{
+    Regex,
+    Terminal,
+    TerminalExt,
+};
+
+use crate::pcre2;
+
+pub struct Search {
+    bar: SearchBar,
+    entry: SearchEntry,
+    case_sensitive: ToggleButton,
+    regex: ToggleButton,
+    whole_word: ToggleButton,
+    wrap_around: ToggleButton,
+    status: Label,
+    target: RefCell<WeakRef<Terminal>>,
+}
+
+fn toggle(label: &str, tooltip: &str, active: bool) -> ToggleButton {
+    ToggleButton::builder()
+        .label(label)
+        .tooltip_text(tooltip)
+        .active(active)
+        .focus_on_click(false)
+        .build()
+}
+
+impl Search {
+
+    pub fn new() -> Rc<Self> {
+        let entry = SearchEntry::builder()
+            .placeholder_text("Find")
+            .hexpand(true)
+            .build();
+
+        let search = Rc::new(Search {
+            bar: SearchBar::builder().show_close_button(true).build(),
+            entry,
+            case_sensitive: toggle("Aa", "Match case", false),
+            regex: toggle(".*", "Regular expression", false),
+            whole_word: toggle("W", "Whole words", false),
+            wrap_around: toggle("⟲", "Wrap around", true),
+            status: Label::new(None),
+            target: RefCell::new(WeakRef::new()),
+        });
+
+        let previous = Button::from_icon_name("go-up-symbolic");
+        previous.set_tooltip_text(Some("Previous match"));
+        let next = Button::from_icon_name("go-down-symbolic");
+        next.set_tooltip_text(Some("Next match"));
+
+        let row = gtk4::Box::new(Orientation::Horizontal, 4);
+        row.append(&search.entry);
+        row.append(&search.case_sensitive);
+        row.append(&search.regex);
+        row.append(&search.whole_word);
+        row.append(&search.wrap_around);
+        row.append(&previous);
+        row.append(&next);
+        row.append(&search.status);
+
+        search.bar.set_child(Some(&row));
+        search.bar.connect_entry(&search.entry);
+
+        // searching backwards (up into the history) is the common case
+        let weak = Rc::downgrade(&search);
+        search.entry.connect_search_changed(move |_| {
+            if let Some(search) = weak.upgrade() {
+                search.update_regex();
+                search.find(true);
+            }
+        });
+
+        let weak = Rc::downgrade(&search);
+        search.entry.connect_activate(move |_| {
+            if let Some(search) = weak.upgrade() {
+                search.find(true);
+            }
+        });
+
+        let weak = Rc::downgrade(&search);
+        search.entry.connect_previous_match(move |_| {
+            if let Some(search) = weak.upgrade() {
+                search.find(true);
+            }
+        });
+
+        let weak = Rc::downgrade(&search);
+        search.entry.connect_next_match(move |_| {
+            if let Some(search) = weak.upgrade() {
+                search.find(false);
+            }
+        });
+
+        let weak = Rc::downgrade(&search);
+        previous.connect_clicked(move |_| {
+            if let Some(search) = weak.upgrade() {
+                search.find(true);
+            }
+        });
+
+        let weak = Rc::downgrade(&search);
+        next.connect_clicked(move |_| {
+            if let Some(search) = weak.upgrade() {
+                search.find(false);
+            }
+        });
+
+        for button in [&search.case_sensitive, &search.regex, &search.whole_word] {
+            let weak = Rc::downgrade(&search);
+            button.connect_toggled(move |_| {
+                if let Some(search) = weak.upgrade() {
+                    search.update_regex();
+                }
+            });
+        }
+
+        let weak = Rc::downgrade(&search);
+        search.wrap_around.connect_toggled(move |button| {
+            if let Some(terminal) = weak.upgrade().and_then(|search| search.target.borrow().upgrade()) {
+                terminal.search_set_wrap_around(button.is_active());
+            }
+        });
+
+        // Clear the highlight and give the focus back when the bar closes
+        let weak = Rc::downgrade(&search);
+        search.bar.connect_search_mode_enabled_notify(move |bar| {
+            if bar.is_search_mode() {
+                return;
+            }
+            if let Some(terminal) = weak.upgrade().and_then(|search| search.target.borrow().upgrade()) {
+                terminal.search_set_regex(None, 0);
+                terminal.unselect_all();
+                terminal.grab_focus();
+            }
+        });
+
+        search
+    }
+
+    pub fn widget(&self) -> &SearchBar {
+        &self.bar
+    }
+
+    // Shows the bar, searching in `terminal`
+    pub fn open(&self, terminal: &Terminal) {
+        if let Some(previous) = self.target.borrow().upgrade() {
+            if &previous != terminal {
+                previous.search_set_regex(None, 0);
+            }
+        }
+        self.target.replace(terminal.downgrade());
+        terminal.search_set_wrap_around(self.wrap_around.is_active());
+
+        self.bar.set_search_mode(true);
+        self.entry.grab_focus();
+        self.update_regex();
+    }
+
+    // Builds the regex from the entry and the toggles and hands it to the
+    // terminal
+    fn update_regex(&self) {
+        let Some(terminal) = self.target.borrow().upgrade() else {
+            return;
+        };
+
+        self.set_status(None);
+
+        let text = self.entry.text();
+        if text.is_empty() {
+            terminal.search_set_regex(None, 0);
+            return;
+        }
+
+        let mut pattern = if self.regex.is_active() {
+            text.to_string()
+        } else {
+            glib::Regex::escape_string(text.as_str()).to_string()
+        };
+
+        if self.whole_word.is_active() {
+            pattern = format!(r"\b(?:{})\b", pattern);
+        }
+
+        let mut flags = pcre2::DEFAULT;
+        if !self.case_sensitive.is_active() {
+            flags |= pcre2::CASELESS;
+        }
+
+        match Regex::for_search(&pattern, flags) {
+            Ok(regex) => terminal.search_set_regex(Some(&regex), 0),
+            Err(_) => {
+                terminal.search_set_regex(None, 0);
+                self.set_status(Some("Invalid pattern"));
+            }
+        }
+    }
+
+    // Moves to the previous (older) or next match
+    pub fn find(&self, backwards: bool) {
+        let Some(terminal) = self.target.borrow().upgrade() else {
+            return;
+        };
+        if terminal.search_get_regex().is_none() {
+            return;
+        }
+
+        let found = if backwards {
+            terminal.search_find_previous()
+        } else {
+            terminal.search_find_next()
+        };
+
+        self.set_status(if found { None } else { Some("No match") });
+    }
+
+    fn set_status(&self, message: Option<&str>) {
+        self.status.set_text(message.unwrap_or_default());
+        if message.is_some() {
+            self.entry.add_css_class("error");
+        } else {
+            self.entry.remove_css_class("error");
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//
+// Session save and restore
+//
+// When the last window or its last tab closes, and when termint quits
+// (app.quit(), logout, SIGTERM), the open windows are written to
+// session.ini next to termint.ini: the size of every window and, for
+// every tab, the layout of its panes with the working directory, profile
+// and command of each terminal. With --restore (or restore_session = true
+// in [Settings]) they are opened again on the next start. Only the
+// instance that owns the session saves it: one started with --restore,
+// or the unique instance of an --app-id (where restore_session applies);
+// other instances are separate and leave session.ini alone.
+//
+// [Window0]
+// width = 680
+// height = 364
+// maximized = false
+// current_tab = 0
+//
+// [Window0.Tab0]
+// layout = h(p,v(p,p))
+// terminal0.working_directory = /home/me/src
+// terminal0.profile = dev
+// terminal0.command =
+//
+
+use std::{
+    env,
+    fs,
+    path::Path,
+    rc::Rc,
+};
+
+use gtk4::{
+    prelude::*,
+    Application,
+    ApplicationWindow,
+    Widget,
+};
+
+use ini::{Ini, Properties};
+
+use vte4::Terminal;
+
+use crate::{
+    cwd,
+    panes::{self, Layout},
+    profiles,
+    remote::Registry,
+    AppTerm,
+    Launch,
+    TermOptions,
+};
+
+// The working directory of a terminal: the one the shell reports (OSC 7)
+// or the one it was started in
+fn working_directory(terminal: &Terminal, registry: &Registry) -> String {
+    cwd::of(terminal)
+        .or_else(|| registry.started_with(terminal).map(|(_, dir)| dir))
+        .unwrap_or_default()
+}
+
+// Writes the open windows to `path`; with none left it keeps the last
+// session
+pub fn save(app: &Application, registry: &Registry, path: &Path) {
+    let mut ini = Ini::new();
+    let mut count = 0;
+
+    for window in app.windows() {
+        let Some(window) = window.downcast_ref::<ApplicationWindow>() else {
+            continue;
+        };
+        let Some(notebook) = AppTerm::notebook_of(window) else {
+            continue;
+        };
+        // the hidden drop-down window is left out
+        if !window.is_visible() || notebook.n_pages() == 0 {
+            continue;
+        }
+
+        let name = format!("Window{}", count);
+        count += 1;
+
+        let (width, height) = window.default_size();
+        ini.with_section(Some(name.as_str()))
+            .set("width", width.to_string())
+            .set("height", height.to_string())
+            .set("maximized", window.is_maximized().to_string())
+            .set("current_tab", notebook.current_page().unwrap_or(0).to_string());
+
+        for page_num in 0..notebook.n_pages() {
+            let Some(page) = notebook.nth_page(Some(page_num)) else {
+                continue;
+            };
+            let Some(layout) = Layout::of(&page) else {
+                continue;
+            };
+
+            let mut section = ini.with_section(Some(format!("{}.Tab{}", name, page_num)));
+            section.set("layout", layout.to_string());
+
+            for (i, terminal) in panes::terminals(&page).iter().enumerate() {
+                let command = registry.started_with(terminal)
+                    .map(|(command, _)| command)
+                    .unwrap_or_default();
+                section
+                    .set(format!("terminal{}.working_directory", i), working_directory(terminal, registry))
+                    .set(format!("terminal{}.profile", i), registry.profile_of(terminal).unwrap_or_default())
+                    .set(format!("terminal{}.command", i), command);
+            }
+        }
+    }
+
+    if count == 0 {
+        return;
+    }
+
+    if let Some(dir) = path.parent() {
+        if let Err(err) = fs::create_dir_all(dir) {
+            eprintln!("Failed to create {}: {}", dir.display(), err);
+            return;
+        }
+    }
+
+    if let Err(err) = ini.write_to_file(path) {
+        eprintln!("Failed to save the session to {}: {}", path.display(), err);
+    }
+}
+
+// What one terminal of a saved tab runs; the shell and the environment
+// come from its profile, as with --profile, not from the command line of
+// this instance
+fn launch_of(app_term: &AppTerm, tab: &Properties, i: usize) -> Launch {
+    let get = |key: &str| tab.get(format!("terminal{}.{}", i, key)).unwrap_or_default().to_string();
+
+    let working_dir = get("working_directory");
+    let name = get("profile");
+    let section = profiles::section(&app_term.config().ini(), &name)
+        .filter(|_| !name.is_empty())
+        .cloned();
+    let settings = section.clone().unwrap_or_default();
+
+    let login_shell = settings.get("shell")
+        .map(str::trim)
+        .filter(|shell| !shell.is_empty())
+        .map(str::to_string)
+        .or_else(|| env::var("SHELL").ok())
+        .unwrap_or_else(|| app_term.launch.login_shell.clone());
+
+    Launch {
+        login_shell,
+        command: get("command"),
+        working_dir: if Path::new(&working_dir).is_dir() { working_dir } else { String::new() },
+        env: profiles::env(&settings),
+        clean_env: false,
+        profile: section.map(|_| name),
+        dropdown: false,
+        toggle: false,
+        record: None,
+        play: None,
+        dump_on_exit: None,
+        ..app_term.launch.clone()
+    }
+}
+
+// Builds `layout` in the place of `anchor`, the pane of its first
+// terminal; `first` is the index of that terminal in the tab
+fn build(app_term: &AppTerm, tab: &Properties, layout: &Layout, anchor: &Widget, first: usize) {
+    let Layout::Split(orientation, start, end) = layout else {
+        return;
+    };
+    let second = first + start.panes();
+    let launch = launch_of(app_term, tab, second);
+    let options: Rc<TermOptions> = app_term.term_options(&launch);
+    let (pane, _) = AppTerm::make_pane(&options, &launch.command);
+    panes::split(anchor, pane.upcast_ref(), *orientation);
+
+    build(app_term, tab, start, anchor, first);
+    build(app_term, tab, end, pane.upcast_ref(), second);
+}
+
+// Opens the windows saved in `path`, returns false if there were none
+pub fn restore(app_term: &AppTerm, app: &Application, path: &Path) -> bool {
+    if !path.exists() {
+        return false;
+    }
+    let ini = match Ini::load_from_file(path) {
+        Ok(ini) => ini,
+        Err(err) => {
+            eprintln!("Failed to read the session {}: {}", path.display(), err);
+            return false;
+        }
+    };
+
+    let mut restored = false;
+
+    for w in 0.. {
+        let name = format!("Window{}", w);
+        let Some(settings) = ini.section(Some(name.as_str())) else {
+            break;
+        };
+
+        let size = |key: &str| settings.get(key).and_then(|v| v.parse::<usize>().ok()).unwrap_or(0);
+        let mut window: Option<ApplicationWindow> = None;
+
+        for t in 0.. {
+            let Some(tab) = ini.section(Some(format!("{}.Tab{}", name, t))) else {
+                break;
+            };
+            let layout = tab.get("layout").and_then(Layout::parse).unwrap_or(Layout::Pane);
+            let launch = launch_of(app_term, tab, 0);
+
+            let terminal = match &window {
+                None => {
+                    let launch = Launch {
+                        window_size: (size("width"), size("height")),
+                        ..launch.clone()
+                    };
+                    window = app_term.open_window(app, &launch);
+                    window.as_ref()
+                        .and_then(|window| panes::terminals(window.upcast_ref()).into_iter().next())
+                }
+                Some(window) => AppTerm::notebook_of(window).map(|notebook| {
+                    AppTerm::add_tab(&notebook, &app_term.term_options(&launch), &launch.command)
+                }),
+            };
+
+            if let Some(anchor) = terminal.as_ref().and_then(panes::pane_of) {
+                build(app_term, tab, &layout, &anchor, 0);
+            }
+        }
+
+        let Some(window) = window else {
+            continue;
+        };
+        restored = true;
+
+        if let Some(notebook) = AppTerm::notebook_of(&window) {
+            let current = settings.get("current_tab").and_then(|v| v.parse::<u32>().ok());
+            if let Some(page_num) = current.filter(|n| *n < notebook.n_pages()) {
+                notebook.set_current_page(Some(page_num));
+            }
+        }
+        if settings.get("maximized") == Some("true") {
+            window.maximize();
+        }
+    }
+
+    restored
+}
@@ -0,0 +1,162 @@
+//
+// Font zoom
+//
+// Ctrl+Plus, Ctrl+Minus and Ctrl+0 (or Ctrl+scroll) change the font
+// scale of the focused terminal and briefly show the new size. Read from
+// the [Settings] section of termint.ini:
+//
+// remember_zoom = false    (save the last zoom and start new terminals with it)
+//
+// The last zoom is kept in state.ini, not in termint.ini.
+//
+
+use std::{rc::Rc, time::Duration};
+
+use gtk4::{
+    gdk,
+    glib,
+    prelude::*,
+    EventControllerScroll,
+    EventControllerScrollFlags,
+    Label,
+    Popover,
+    PositionType,
+    PropagationPhase,
+};
+
+use ini::Ini;
+
+use vte4::{
+    Terminal,
+    TerminalExt,
+};
+
+use crate::{
+    config::Config,
+    fonts::{MAX_FONT_SCALE, MIN_FONT_SCALE},
+    get_bool,
+};
+
+const STEP: f64 = 1.1;
+
+// How long the size stays on screen
+const INDICATOR_TIMEOUT: Duration = Duration::from_millis(1000);
+const INDICATOR_CLASS: &str = "zoom-indicator";
+
+pub fn default_settings(settings: &mut ini::SectionSetter) {
+    settings.set("remember_zoom", "false");
+}
+
+// The zoom new terminals start with, if it is remembered
+pub fn saved(ini: &Ini, state: &Ini) -> Option<f64> {
+    let settings = ini.section(Some("Settings"))?;
+    if !get_bool(settings, "remember_zoom", false) {
+        return None;
+    }
+    let value = state.general_section().get("zoom")?.trim();
+    match value.parse::<f64>() {
+        Ok(zoom) if zoom.is_finite() && zoom > 0.0 => Some(zoom),
+        _ => {
+            eprintln!("Warning: invalid value \"{}\" for \"zoom\"", value);
+            None
+        }
+    }
+}
+
+pub fn zoom_in(terminal: &Terminal, config: &Rc<Config>) {
+    set(terminal, config, terminal.font_scale() * STEP);
+}
+
+pub fn zoom_out(terminal: &Terminal, config: &Rc<Config>) {
+    set(terminal, config, terminal.font_scale() / STEP);
+}
+
+pub fn reset(terminal: &Terminal, config: &Rc<Config>) {
+    set(terminal, config, config.font_scale(terminal));
+}
+
+fn set(terminal: &Terminal, config: &Rc<Config>, scale: f64) {
+    let scale = scale.clamp(MIN_FONT_SCALE, MAX_FONT_SCALE);
+    terminal.set_font_scale(scale);
+    show(terminal);
+
+    let remember = config.ini()
+        .section(Some("Settings"))
+        .map(|settings| get_bool(settings, "remember_zoom", false))
+        .unwrap_or(false);
+    if remember {
+        // the zoom is kept relative to font_scale
+        let zoom = scale / config.font_scale(terminal);
+        config.save_state("zoom", format!("{:.3}", zoom));
+    }
+}
+
+// Ctrl+scroll zooms instead of scrolling
+pub fn setup(terminal: &Terminal, config: &Rc<Config>) {
+    let scroll = EventControllerScroll::new(
+        EventControllerScrollFlags::VERTICAL | EventControllerScrollFlags::DISCRETE,
+    );
+    // run before the terminal scrolls
+    scroll.set_propagation_phase(PropagationPhase::Capture);
+
+    let config = Rc::downgrade(config);
+    scroll.connect_scroll(move |controller, _, dy| {
+        if !controller.current_event_state().contains(gdk::ModifierType::CONTROL_MASK) {
+            return glib::Propagation::Proceed;
+        }
+        let (Ok(terminal), Some(config)) = (controller.widget().downcast::<Terminal>(), config.upgrade()) else {
+            return glib::Propagation::Proceed;
+        };
+        if dy < 0.0 {
+            zoom_in(&terminal, &config);
+        } else if dy > 0.0 {
+            zoom_out(&terminal, &config);
+        }
+        glib::Propagation::Stop
+    });
+    terminal.add_controller(scroll);
+}
+
+// Shows the zoom and font size over the terminal for a moment
+fn show(terminal: &Terminal) {
+    // replace the indicator of the previous step
+    let mut child = terminal.first_child();
+    while let Some(widget) = child {
+        child = widget.next_sibling();
+        if widget.has_css_class(INDICATOR_CLASS) {
+            widget.unparent();
+        }
+    }
+
+    let scale = terminal.font_scale();
+    let mut text = format!("{:.0}%", scale * 100.0);
+    if let Some(desc) = terminal.font_desc() {
+        let size = desc.size() as f64 / gtk4::pango::SCALE as f64 * scale;
+        if size > 0.0 {
+            text.push_str(&format!(" · {:.1} pt", size));
+        }
+    }
+
+    let popover = Popover::builder()
+        .child(&Label::new(Some(&text)))
+        .autohide(false)
+        .can_focus(false)
+        .has_arrow(false)
+        .position(PositionType::Bottom)
+        .css_classes([INDICATOR_CLASS])
+        .build();
+    popover.set_parent(terminal);
+    let (width, height) = (terminal.width(), terminal.height());
+    popover.set_pointing_to(Some(&gdk::Rectangle::new(width / 2, height / 2, 1, 1)));
+    popover.popup();
+
+    let popover = popover.downgrade();
+    glib::timeout_add_local_once(INDICATOR_TIMEOUT, move || {
+        if let Some(popover) = popover.upgrade() {
+            if popover.parent().is_some() {
+                popover.popdown();
+                popover.unparent();
+            }
+        }
+    });
+}